@@ -1,6 +1,6 @@
 extern crate mpd;
 
-use mpd::{Client, Query};
+use mpd::{Client, Expression, Query, Term};
 use std::net::TcpStream;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -17,8 +17,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("No song playing.");
     }
 
-    let query = Query::Expression(r#"(artist == "Miles Davis")"#.to_string());
-    for song in c.search(&query, None)? {
+    let query = Query::Expression(Expression::eq(Term::Tag("artist"), "Miles Davis"));
+    for song in c.search(&query, None, None)? {
         println!("{}", song.file);
     }
 
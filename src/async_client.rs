@@ -0,0 +1,1445 @@
+//! The module defines an asynchronous client, running on top of tokio streams
+//!
+//! [`AsyncClient`](struct.AsyncClient.html) has the same commands as the blocking
+//! [`Client`](../client/struct.Client.html), but every command is an `async fn`.
+//! Commands are defined once for both clients: a response is read completely,
+//! and then parsed by the same code as in `Client`, so results are the same for both of them.
+//! Command lists and pipelines are available as [`AsyncCommandList`](struct.AsyncCommandList.html)
+//! and [`AsyncPipeline`](struct.AsyncPipeline.html).
+//!
+//! Idle mode is exposed as an [`IdleStream`](struct.IdleStream.html) of changed subsystems,
+//! which re-enters idle mode after each batch of events:
+//!
+//! ```rust,no_run
+//! # async fn run() -> mpd::error::Result<()> {
+//! use mpd::{AsyncClient, Subsystem};
+//!
+//! let mut conn = AsyncClient::connect("127.0.0.1:6600").await?;
+//! println!("{:?}", conn.status().await?);
+//!
+//! let mut events = conn.idle(&[Subsystem::Player]);
+//! while let Some(event) = events.next_event().await {
+//!     println!("{:?}", event?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available with `tokio` feature.
+
+use crate::client::{Limits, LossyLine, DEFAULT_BINARY_LIMIT};
+use crate::codec::{Decoder, Event};
+use crate::command_list::{self, CommandReply, Commands, Replies, Ticket};
+use crate::connect::ConnectOptions;
+use crate::error::{Error, ErrorCode, ProtoError, Result};
+use crate::idle::Subsystem;
+use crate::list::ListGroup;
+use crate::lsinfo::LsInfoResponse;
+use crate::message::{Channel, Message};
+use crate::mount::{Mount, Neighbor};
+use crate::output::Output;
+use crate::picture::{Picture, PictureInfo};
+use crate::pipeline;
+use crate::playlist::Playlist;
+use crate::plugin::Plugin;
+use crate::proto::Replay;
+use crate::reply::Response;
+use crate::request::{self, Request, Transfer};
+use crate::search::{Query, Sort, Term, Window};
+use crate::song::{Position, Range, Song};
+use crate::stats::{Count, Stats};
+use crate::status::{ReplayGain, Status};
+use crate::version::{check_version, Version};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// AsyncClient {{{
+
+/// Asynchronous client connection
+#[derive(Debug)]
+pub struct AsyncClient<S = TcpStream>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    socket: S,
+    decoder: Decoder,
+    /// MPD version
+    pub version: Version,
+    binary_limit: usize,
+    max_binary_limit: Option<usize>,
+    commands: Option<HashSet<String>>,
+    idling: bool,
+    // responses to read and discard before the next command (from dropped futures and pipelines)
+    skip: usize,
+    // events of a response, which is not read completely yet
+    partial: Vec<Result<Event>>,
+}
+
+impl AsyncClient<TcpStream> {
+    /// Connect to MPD server over TCP
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<AsyncClient<TcpStream>> {
+        ConnectOptions::new().connect_async(addr).await
+    }
+}
+
+#[cfg(unix)]
+impl AsyncClient<UnixStream> {
+    /// Connect to MPD server over a Unix domain socket
+    ///
+    /// A path starting with `@` is a Linux abstract socket name.
+    pub async fn connect_unix<P: AsRef<Path>>(path: P) -> Result<AsyncClient<UnixStream>> {
+        ConnectOptions::new().connect_unix_async(path).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncClient<S> {
+    // Constructors {{{
+    /// Create client from an already connected stream
+    pub async fn new(socket: S) -> Result<AsyncClient<S>> {
+        AsyncClient::with_options(socket, &ConnectOptions::new()).await
+    }
+
+    /// Create client from an already connected stream, and set it up with given options
+    ///
+    /// Timeouts are not applied, see [`ConnectOptions::connect_async`](../connect/struct.ConnectOptions.html#method.connect_async).
+    pub async fn with_options(socket: S, options: &ConnectOptions) -> Result<AsyncClient<S>> {
+        let mut client = AsyncClient {
+            socket,
+            decoder: Decoder::new(),
+            version: Version(0, 0, 0),
+            binary_limit: DEFAULT_BINARY_LIMIT,
+            max_binary_limit: None,
+            commands: None,
+            idling: false,
+            skip: 0,
+            partial: Vec::new(),
+        };
+
+        client.version = match client.read_event().await? {
+            Event::Greeting(version) => version,
+            _ => return Err(Error::Proto(ProtoError::BadBanner)),
+        };
+
+        for request in options.setup() {
+            client.execute(request).await?;
+        }
+        if let Some(size) = options.initial_binary_limit() {
+            client.binary_limit = size;
+        }
+        Ok(client)
+    }
+    // }}}
+
+    /// Start a command list, to send several commands in a single round trip
+    ///
+    /// See [`CommandList`](../command_list/struct.CommandList.html) for details.
+    pub fn command_list(&mut self) -> AsyncCommandList<'_, S> {
+        AsyncCommandList {
+            commands: Commands::new(self.version),
+            client: self,
+        }
+    }
+
+    /// Start a pipeline, to send several commands at once and read their replies later
+    ///
+    /// See [`Pipeline`](../pipeline/struct.Pipeline.html) for details.
+    pub fn pipeline(&mut self) -> AsyncPipeline<'_, S> {
+        AsyncPipeline {
+            commands: Commands::new(self.version),
+            client: self,
+        }
+    }
+
+    /// Get the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.socket
+    }
+
+    /// Run arbitrary command and read its response
+    ///
+    /// See [`Client::raw_command`](../client/struct.Client.html#method.raw_command).
+    pub async fn raw_command(&mut self, command: &str, arguments: &[&str]) -> Result<Response> {
+        self.execute(request::raw(command, arguments)).await
+    }
+
+    /// Set how to handle response lines which are not valid UTF-8
+    ///
+    /// See [`Client::set_lossy_utf8`](../client/struct.Client.html#method.set_lossy_utf8).
+    pub fn set_lossy_utf8(&mut self, lossy: bool) {
+        self.decoder.set_lossy_utf8(lossy);
+    }
+
+    /// Take all lines decoded lossily since the last call
+    pub fn take_lossy_lines(&mut self) -> Vec<LossyLine> {
+        self.decoder.take_lossy_lines()
+    }
+
+    /// Set limits on server responses
+    ///
+    /// See [`Limits`](../client/struct.Limits.html) for details.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Get current limits on server responses
+    pub fn limits(&self) -> Limits {
+        self.decoder.limits()
+    }
+
+    // Playback options & status {{{
+    /// Get MPD status
+    pub async fn status(&mut self) -> Result<Status> {
+        self.execute(request::full_status()).await
+    }
+
+    /// Get MPD playing statistics
+    pub async fn stats(&mut self) -> Result<Stats> {
+        self.execute(request::stats()).await
+    }
+
+    /// Clear error state
+    pub async fn clearerror(&mut self) -> Result<()> {
+        self.execute(request::clearerror()).await
+    }
+
+    /// Set volume
+    pub async fn volume(&mut self, volume: i8) -> Result<()> {
+        self.execute(request::volume(volume)).await
+    }
+
+    /// Set repeat state
+    pub async fn repeat(&mut self, value: bool) -> Result<()> {
+        self.execute(request::repeat(value)).await
+    }
+
+    /// Set random state
+    pub async fn random(&mut self, value: bool) -> Result<()> {
+        self.execute(request::random(value)).await
+    }
+
+    /// Set single state
+    pub async fn single(&mut self, value: bool) -> Result<()> {
+        self.execute(request::single(value)).await
+    }
+
+    /// Set consume state
+    pub async fn consume(&mut self, value: bool) -> Result<()> {
+        self.execute(request::consume(value)).await
+    }
+
+    /// Set crossfade time in seconds
+    pub async fn crossfade(&mut self, value: u32) -> Result<()> {
+        self.execute(request::crossfade(value)).await
+    }
+
+    /// Set mixramp level in dB
+    pub async fn mixrampdb(&mut self, value: f32) -> Result<()> {
+        self.execute(request::mixrampdb(value)).await
+    }
+
+    /// Set mixramp delay in seconds
+    pub async fn mixrampdelay(&mut self, value: u32) -> Result<()> {
+        self.execute(request::mixrampdelay(value)).await
+    }
+
+    /// Set replay gain mode
+    pub async fn replaygain(&mut self, gain: ReplayGain) -> Result<()> {
+        self.execute(request::replaygain(gain)).await
+    }
+    // }}}
+
+    // Playback control {{{
+    /// Start playback
+    pub async fn play(&mut self) -> Result<()> {
+        self.execute(request::play()).await
+    }
+
+    /// Start playback from given position in a queue
+    pub async fn play_from_position(&mut self, place: u32) -> Result<()> {
+        self.execute(request::play_from_position(place)).await
+    }
+
+    /// Start playback from given id in a queue
+    pub async fn play_from_id(&mut self, place: u32) -> Result<()> {
+        self.execute(request::play_from_id(place)).await
+    }
+
+    /// Switch to a next song in queue
+    pub async fn next(&mut self) -> Result<()> {
+        self.execute(request::next()).await
+    }
+
+    /// Switch to a previous song in queue
+    pub async fn prev(&mut self) -> Result<()> {
+        self.execute(request::prev()).await
+    }
+
+    /// Stop playback
+    pub async fn stop(&mut self) -> Result<()> {
+        self.execute(request::stop()).await
+    }
+
+    /// Toggle pause state
+    pub async fn toggle_pause(&mut self) -> Result<()> {
+        self.execute(request::toggle_pause()).await
+    }
+
+    /// Set pause state
+    pub async fn pause(&mut self, value: bool) -> Result<()> {
+        self.execute(request::pause(value)).await
+    }
+
+    /// Seek to a given place (in seconds) in a song identified by position
+    pub async fn seek(&mut self, place: u32, pos: u32) -> Result<()> {
+        self.execute(request::seek(place, pos)).await
+    }
+
+    /// Seek to a given place (in seconds) in a song identified by id
+    pub async fn seek_id(&mut self, place: u32, pos: u32) -> Result<()> {
+        self.execute(request::seek_id(place, pos)).await
+    }
+
+    /// Seek to a given place (in seconds) in the current song
+    pub async fn rewind(&mut self, pos: u32) -> Result<()> {
+        self.execute(request::rewind(pos)).await
+    }
+    // }}}
+
+    // Queue control {{{
+    /// List given song or range of songs in a play queue
+    pub async fn playlistinfo<T: Into<Range>>(&mut self, pos: T) -> Result<Vec<Song>> {
+        self.execute(request::playlistinfo(pos.into())).await
+    }
+
+    /// List given song in a play queue
+    pub async fn playlistid(&mut self, pos: u32) -> Result<Song> {
+        self.execute(request::playlistid(pos)).await
+    }
+
+    /// List all songs in a play queue
+    pub async fn queue(&mut self) -> Result<Vec<Song>> {
+        self.execute(request::queue()).await
+    }
+
+    /// Get current playing song
+    pub async fn currentsong(&mut self) -> Result<Option<Song>> {
+        self.execute(request::currentsong()).await
+    }
+
+    /// Clear current queue
+    pub async fn clear(&mut self) -> Result<()> {
+        self.execute(request::clear()).await
+    }
+
+    /// List all changes in a queue since given version
+    pub async fn changes(&mut self, version: u32) -> Result<Vec<Song>> {
+        self.execute(request::changes(version)).await
+    }
+
+    /// Append all songs in path (directories add recursively) to the playlist
+    pub async fn add(&mut self, path: &str) -> Result<()> {
+        self.execute(request::add(path)).await
+    }
+
+    /// Append a song into a queue
+    pub async fn push(&mut self, path: &str) -> Result<u32> {
+        self.execute(request::push(path)).await
+    }
+
+    /// Insert a song into a given position in a queue
+    pub async fn insert(&mut self, path: &str, pos: usize) -> Result<usize> {
+        self.execute(request::insert(path, pos)).await.map(|id| id as usize)
+    }
+
+    /// Delete several songs (in a range) from a queue
+    pub async fn delete<T: Into<Range>>(&mut self, pos: T) -> Result<()> {
+        self.execute(request::delete(pos.into())).await
+    }
+
+    /// Delete a song from a queue
+    pub async fn deleteid(&mut self, pos: u32) -> Result<()> {
+        self.execute(request::deleteid(pos)).await
+    }
+
+    /// Move a song (at a some position) or several songs (in a range) to other position in queue
+    pub async fn move_range<T: Into<Range>>(&mut self, from: T, to: usize) -> Result<()> {
+        self.execute(request::move_range(from.into(), to)).await
+    }
+
+    /// Move a song (at a some position) or several songs (in a range) to other position in queue
+    pub async fn moveid(&mut self, from: u32, to: usize) -> Result<()> {
+        self.execute(request::moveid(from, to)).await
+    }
+
+    /// Swap two songs identified by queue position in a queue
+    pub async fn swap(&mut self, one: u32, two: u32) -> Result<()> {
+        self.execute(request::swap(one, two)).await
+    }
+
+    /// Swap two songs identified by id in a queue
+    pub async fn swapid(&mut self, one: u32, two: u32) -> Result<()> {
+        self.execute(request::swapid(one, two)).await
+    }
+
+    /// Shuffle queue in a given range (use `..` to shuffle full queue)
+    pub async fn shuffle<T: Into<Range>>(&mut self, range: T) -> Result<()> {
+        self.execute(request::shuffle(range.into())).await
+    }
+
+    /// Set song priority in a queue
+    pub async fn prio<T: Into<Range>>(&mut self, pos: T, prio: u8) -> Result<()> {
+        self.execute(request::prio(pos.into(), prio)).await
+    }
+
+    /// Set song priority in a queue
+    pub async fn prioid(&mut self, pos: u32, prio: u8) -> Result<()> {
+        self.execute(request::prioid(pos, prio)).await
+    }
+
+    /// Set song range (in seconds) to play
+    ///
+    /// Doesn't work for currently playing song.
+    pub async fn range<T: Into<Range>>(&mut self, song: u32, range: T) -> Result<()> {
+        self.execute(request::range(song, range.into())).await
+    }
+
+    /// Add tag to a song
+    pub async fn tag(&mut self, song: u32, tag: &str, value: &str) -> Result<()> {
+        self.execute(request::tag(song, tag, value)).await
+    }
+
+    /// Delete tag from a song
+    pub async fn untag(&mut self, song: u32, tag: &str) -> Result<()> {
+        self.execute(request::untag(song, tag)).await
+    }
+    // }}}
+
+    // Connection settings {{{
+    /// Just pings MPD server, does nothing
+    pub async fn ping(&mut self) -> Result<()> {
+        self.execute(request::ping()).await
+    }
+
+    /// Close MPD connection
+    pub async fn close(&mut self) -> Result<()> {
+        self.execute(request::close()).await
+    }
+
+    /// Kill MPD server
+    pub async fn kill(&mut self) -> Result<()> {
+        self.execute(request::kill()).await
+    }
+
+    /// Login to MPD server with given password
+    pub async fn login(&mut self, password: &str) -> Result<()> {
+        // Permissions may change, so allowed commands should be fetched again
+        self.commands = None;
+        self.execute(request::login(password)).await
+    }
+
+    /// Set the maximum binary response size for the current connection to the specified number of bytes.
+    pub async fn binarylimit(&mut self, size: usize) -> Result<()> {
+        self.execute(request::binarylimit(size)).await?;
+        self.binary_limit = size;
+        Ok(())
+    }
+
+    /// Get the maximum binary response size negotiated for the current connection
+    pub fn binary_limit(&self) -> usize {
+        self.binary_limit
+    }
+
+    /// Allow binary transfers (like `albumart`) to temporarily raise binary limit up to the given size
+    ///
+    /// See [`Client::set_max_binary_limit`](../client/struct.Client.html#method.set_max_binary_limit).
+    pub fn set_max_binary_limit(&mut self, size: Option<usize>) {
+        self.max_binary_limit = size;
+    }
+    // }}}
+
+    // Playlist methods {{{
+    /// List all playlists
+    pub async fn playlists(&mut self) -> Result<Vec<Playlist>> {
+        self.execute(request::playlists()).await
+    }
+
+    /// List all songs in a playlist
+    pub async fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
+        self.execute(request::playlist(name)).await
+    }
+
+    /// Load playlist into queue
+    ///
+    /// You can give either full range (`..`) to load all songs in a playlist,
+    /// or some partial range to load only part of playlist.
+    pub async fn load<T: Into<Range>>(&mut self, name: &str, range: T) -> Result<()> {
+        self.execute(request::load(name, range.into())).await
+    }
+
+    /// Save current queue into playlist
+    ///
+    /// If playlist with given name doesn't exist, create new one.
+    pub async fn save(&mut self, name: &str) -> Result<()> {
+        self.execute(request::save(name)).await
+    }
+
+    /// Rename playlist
+    pub async fn pl_rename(&mut self, name: &str, newname: &str) -> Result<()> {
+        self.execute(request::pl_rename(name, newname)).await
+    }
+
+    /// Clear playlist
+    pub async fn pl_clear(&mut self, name: &str) -> Result<()> {
+        self.execute(request::pl_clear(name)).await
+    }
+
+    /// Delete playlist
+    pub async fn pl_remove(&mut self, name: &str) -> Result<()> {
+        self.execute(request::pl_remove(name)).await
+    }
+
+    /// Add new songs to a playlist
+    pub async fn pl_push(&mut self, name: &str, path: &str) -> Result<()> {
+        self.execute(request::pl_push(name, path)).await
+    }
+
+    /// Delete a song at a given position in a playlist
+    pub async fn pl_delete(&mut self, name: &str, pos: u32) -> Result<()> {
+        self.execute(request::pl_delete(name, pos)).await
+    }
+
+    /// Move song in a playlist from one position into another
+    pub async fn pl_shift(&mut self, name: &str, from: u32, to: u32) -> Result<()> {
+        self.execute(request::pl_shift(name, from, to)).await
+    }
+    // }}}
+
+    // Database methods {{{
+    /// Run database rescan, i.e. remove non-existing files from DB
+    /// as well as add new files to DB
+    pub async fn rescan(&mut self) -> Result<u32> {
+        self.execute(request::rescan()).await
+    }
+
+    /// Run database update, i.e. remove non-existing files from DB
+    pub async fn update(&mut self) -> Result<u32> {
+        self.execute(request::update()).await
+    }
+    // }}}
+
+    // Database search {{{
+    /// List all songs/directories in directory
+    pub async fn listfiles(&mut self, song_path: &str) -> Result<Vec<(String, String)>> {
+        self.execute(request::listfiles(song_path)).await
+    }
+
+    /// Find songs matching Query conditions.
+    ///
+    /// See [`Client::find`](../client/struct.Client.html#method.find) for `sort` and `window` arguments.
+    pub async fn find<'a, O, W>(&mut self, query: &Query<'_>, sort: O, window: W) -> Result<Vec<Song>>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::find("find", query, sort.into(), window.into())).await
+    }
+
+    /// Case-insensitively search for songs matching Query conditions.
+    pub async fn search<'a, O, W>(&mut self, query: &Query<'_>, sort: O, window: W) -> Result<Vec<Song>>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::find("search", query, sort.into(), window.into())).await
+    }
+
+    /// Count songs matching Query conditions and their total playtime.
+    ///
+    /// If `group` is given, the result contains one entry per distinct value of that tag.
+    pub async fn count(&mut self, query: &Query<'_>, group: Option<Term<'_>>) -> Result<Vec<Count>> {
+        self.execute(request::count(query, group)).await
+    }
+
+    /// Lists unique tags values of the specified type for songs matching the given query.
+    pub async fn list<W>(&mut self, term: &Term<'_>, query: &Query<'_>, window: W) -> Result<Vec<String>>
+    where
+        W: Into<Window>,
+    {
+        self.execute(request::list(term, query, window.into())).await
+    }
+
+    /// Lists unique tags values of the specified type for songs matching the given query,
+    /// grouped by one or more other tags.
+    pub async fn list_grouped<W>(&mut self, term: &Term<'_>, query: &Query<'_>, groups: &[Term<'_>], window: W) -> Result<Vec<ListGroup>>
+    where
+        W: Into<Window>,
+    {
+        self.execute(request::list_grouped(term, query, groups, window.into())).await
+    }
+
+    /// Find all songs in the db that match query and adds them to current playlist.
+    ///
+    /// If `pos` is given, songs are inserted at this queue position instead of appended.
+    pub async fn findadd<'a, O, W>(&mut self, query: &Query<'_>, sort: O, window: W, pos: Option<Position>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::findadd("findadd", query, sort.into(), window.into(), pos))
+            .await
+    }
+
+    /// Case-insensitively search for songs matching Query conditions and adds them to current playlist.
+    pub async fn searchadd<'a, O, W>(&mut self, query: &Query<'_>, sort: O, window: W, pos: Option<Position>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::findadd("searchadd", query, sort.into(), window.into(), pos))
+            .await
+    }
+
+    /// Case-insensitively search for songs matching Query conditions and adds them to a playlist.
+    pub async fn searchaddpl<'a, O, W>(&mut self, name: &str, query: &Query<'_>, sort: O, window: W, pos: Option<u32>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::searchaddpl(name, query, sort.into(), window.into(), pos))
+            .await
+    }
+
+    /// Lists the contents of a directory.
+    pub async fn lsinfo(&mut self, path: &str) -> Result<Vec<LsInfoResponse>> {
+        self.execute(request::lsinfo("lsinfo", path)).await
+    }
+
+    /// Lists names of all songs, directories and playlists in a directory, recursively.
+    ///
+    /// Songs only have `file` field set.
+    pub async fn listall(&mut self, path: &str) -> Result<Vec<LsInfoResponse>> {
+        self.execute(request::lsinfo("listall", path)).await
+    }
+
+    /// Lists all songs, directories and playlists with metadata in a directory, recursively.
+    pub async fn listallinfo(&mut self, path: &str) -> Result<Vec<LsInfoResponse>> {
+        self.execute(request::lsinfo("listallinfo", path)).await
+    }
+
+    /// Returns raw metadata for file
+    pub async fn readcomments(&mut self, path: &str) -> Result<Vec<(String, String)>> {
+        self.execute(request::readcomments(path)).await
+    }
+    // }}}
+
+    // Binary transfers {{{
+    /// Find album art for file
+    ///
+    /// Returns `None` if there is no such file.
+    pub async fn albumart(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        self.albumart_to(path, 0, &mut data, |_, _| ()).await.map(|info| info.map(|_| data))
+    }
+
+    /// Stream album art for file into a writer, starting at a given offset
+    ///
+    /// See [`Client::albumart_to`](../client/struct.Client.html#method.albumart_to) for details.
+    pub async fn albumart_to<W, F>(&mut self, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(usize, usize),
+    {
+        match self.read_binary("albumart", path, offset, out, progress).await {
+            Err(Error::Server(ref e)) if e.code == ErrorCode::NoExist => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Read picture embedded into song file
+    ///
+    /// Returns `None` if the song has no embedded picture.
+    pub async fn readpicture(&mut self, path: &str) -> Result<Option<Picture>> {
+        let mut data = Vec::new();
+        self.readpicture_to(path, 0, &mut data, |_, _| ())
+            .await
+            .map(|info| info.map(|info| Picture { data, mime: info.mime }))
+    }
+
+    /// Stream picture embedded into song file into a writer, starting at a given offset
+    pub async fn readpicture_to<W, F>(&mut self, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(usize, usize),
+    {
+        self.read_binary("readpicture", path, offset, out, progress).await
+    }
+
+    /// Find cover art for a song, trying embedded picture first and falling back to album art
+    pub async fn cover_art(&mut self, path: &str) -> Result<Option<Picture>> {
+        match self.readpicture(path).await {
+            Ok(Some(picture)) => Ok(Some(picture)),
+            Ok(None) | Err(Error::Unsupported { .. }) => {
+                self.albumart(path).await.map(|data| data.map(|data| Picture { data, mime: None }))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read_binary<W, F>(&mut self, cmd: &str, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(usize, usize),
+    {
+        let limit = self.binary_limit;
+        let result = self.read_binary_chunks(cmd, path, offset, out, progress).await;
+        if self.binary_limit != limit {
+            let restored = self.binarylimit(limit).await;
+            return result.and_then(|info| restored.map(|_| info));
+        }
+        result
+    }
+
+    async fn read_binary_chunks<W, F>(
+        &mut self,
+        cmd: &str,
+        path: &str,
+        offset: usize,
+        out: &mut W,
+        mut progress: F,
+    ) -> Result<Option<PictureInfo>>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(usize, usize),
+    {
+        let mut transfer = Transfer::new(offset);
+        loop {
+            let mut chunk = match self.execute(request::binary_chunk(cmd, path, transfer.received())).await? {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            };
+
+            // The chunk is read completely before writing it out,
+            // so a failing writer doesn't break the connection
+            out.write_all(&chunk.data).await?;
+            let info = transfer.feed(&mut chunk);
+            progress(transfer.received(), chunk.size);
+            if info.is_some() {
+                out.flush().await?;
+                return Ok(info);
+            }
+
+            if let Some(limit) = transfer.raise_limit(chunk.size, self.binary_limit, self.max_binary_limit) {
+                match self.binarylimit(limit).await {
+                    // Server doesn't support `binarylimit`, go on with the current limit
+                    Ok(()) | Err(Error::Server(_)) | Err(Error::Unsupported { .. }) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    // }}}
+
+    // Output methods {{{
+    /// List all outputs
+    pub async fn outputs(&mut self) -> Result<Vec<Output>> {
+        self.execute(request::outputs()).await
+    }
+
+    /// Set given output enabled state
+    pub async fn output(&mut self, id: u32, state: bool) -> Result<()> {
+        self.execute(request::output(id, state)).await
+    }
+
+    /// Disable given output
+    pub async fn out_disable(&mut self, id: u32) -> Result<()> {
+        self.execute(request::output(id, false)).await
+    }
+
+    /// Enable given output
+    pub async fn out_enable(&mut self, id: u32) -> Result<()> {
+        self.execute(request::output(id, true)).await
+    }
+
+    /// Toggle given output
+    pub async fn out_toggle(&mut self, id: u32) -> Result<()> {
+        self.execute(request::out_toggle(id)).await
+    }
+    // }}}
+
+    // Reflection methods {{{
+    /// Get current music directory
+    pub async fn music_directory(&mut self) -> Result<String> {
+        self.execute(request::music_directory()).await
+    }
+
+    /// List all available commands
+    pub async fn commands(&mut self) -> Result<Vec<String>> {
+        self.execute(request::commands("commands")).await
+    }
+
+    /// List all forbidden commands
+    pub async fn notcommands(&mut self) -> Result<Vec<String>> {
+        self.execute(request::commands("notcommands")).await
+    }
+
+    /// Check if a command (like `readpicture` or `sticker get`) can be used with this connection
+    ///
+    /// See [`Client::supports`](../client/struct.Client.html#method.supports).
+    pub async fn supports(&mut self, command: &str) -> Result<bool> {
+        if check_version(command, self.version).is_err() {
+            return Ok(false);
+        }
+        let commands = match self.commands {
+            Some(ref commands) => commands,
+            None => {
+                let commands = request::allowed_commands(self.commands().await?, self.notcommands().await?);
+                self.commands.get_or_insert(commands)
+            }
+        };
+        Ok(request::is_allowed(commands, command))
+    }
+
+    /// List all available URL handlers
+    pub async fn urlhandlers(&mut self) -> Result<Vec<String>> {
+        self.execute(request::urlhandlers()).await
+    }
+
+    /// List all supported tag types
+    pub async fn tagtypes(&mut self) -> Result<Vec<String>> {
+        self.execute(request::tagtypes()).await
+    }
+
+    /// Disable all tag types in responses for the current connection
+    pub async fn tagtypes_clear(&mut self) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes clear", &[])).await
+    }
+
+    /// Enable all tag types in responses for the current connection
+    pub async fn tagtypes_all(&mut self) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes all", &[])).await
+    }
+
+    /// Enable given tag types in responses for the current connection
+    pub async fn tagtypes_enable(&mut self, tags: &[&str]) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes enable", tags)).await
+    }
+
+    /// Disable given tag types in responses for the current connection
+    pub async fn tagtypes_disable(&mut self, tags: &[&str]) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes disable", tags)).await
+    }
+
+    /// Switch the current connection to a given partition
+    pub async fn partition(&mut self, name: &str) -> Result<()> {
+        self.execute(request::partition(name)).await
+    }
+
+    /// List all available decoder plugins
+    pub async fn decoders(&mut self) -> Result<Vec<Plugin>> {
+        self.execute(request::decoders()).await
+    }
+    // }}}
+
+    // Messaging {{{
+    /// List all channels available for current connection
+    pub async fn channels(&mut self) -> Result<Vec<Channel>> {
+        self.execute(request::channels()).await
+    }
+
+    /// Read queued messages from subscribed channels
+    pub async fn readmessages(&mut self) -> Result<Vec<Message>> {
+        self.execute(request::readmessages()).await
+    }
+
+    /// Send a message to a channel
+    pub async fn sendmessage(&mut self, channel: Channel, message: &str) -> Result<()> {
+        self.execute(request::sendmessage(channel, message)).await
+    }
+
+    /// Subscribe to a channel
+    pub async fn subscribe(&mut self, channel: Channel) -> Result<()> {
+        self.execute(request::subscribe("subscribe", channel)).await
+    }
+
+    /// Unsubscribe to a channel
+    pub async fn unsubscribe(&mut self, channel: Channel) -> Result<()> {
+        self.execute(request::subscribe("unsubscribe", channel)).await
+    }
+    // }}}
+
+    // Mount methods {{{
+    /// List all (virtual) mounts
+    pub async fn mounts(&mut self) -> Result<Vec<Mount>> {
+        self.execute(request::mounts()).await
+    }
+
+    /// List all network neighbors, which can be potentially mounted
+    pub async fn neighbors(&mut self) -> Result<Vec<Neighbor>> {
+        self.execute(request::neighbors()).await
+    }
+
+    /// Mount given neighbor to a mount point
+    pub async fn mount(&mut self, path: &str, uri: &str) -> Result<()> {
+        self.execute(request::mount(path, uri)).await
+    }
+
+    /// Unmount given active (virtual) mount
+    pub async fn unmount(&mut self, path: &str) -> Result<()> {
+        self.execute(request::unmount(path)).await
+    }
+    // }}}
+
+    // Sticker methods {{{
+    /// Show sticker value for a given object, identified by type and uri
+    pub async fn sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<String> {
+        self.execute(request::sticker(typ, uri, name)).await
+    }
+
+    /// Set sticker value for a given object, identified by type and uri
+    pub async fn set_sticker(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<()> {
+        self.execute(request::set_sticker(typ, uri, name, value)).await
+    }
+
+    /// Delete sticker from a given object, identified by type and uri
+    pub async fn delete_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<()> {
+        self.execute(request::delete_sticker(typ, uri, name)).await
+    }
+
+    /// Remove all stickers from a given object, identified by type and uri
+    pub async fn clear_stickers(&mut self, typ: &str, uri: &str) -> Result<()> {
+        self.execute(request::clear_stickers(typ, uri)).await
+    }
+
+    /// List all stickers from a given object, identified by type and uri
+    pub async fn stickers(&mut self, typ: &str, uri: &str) -> Result<Vec<String>> {
+        self.execute(request::stickers(typ, uri)).await
+    }
+
+    /// List all stickers from a given object in a map, identified by type and uri
+    pub async fn stickers_map(&mut self, typ: &str, uri: &str) -> Result<HashMap<String, String>> {
+        self.execute(request::stickers_map(typ, uri)).await
+    }
+
+    /// List all (file, sticker) pairs for sticker name and objects of given type
+    /// from given directory (identified by uri)
+    pub async fn find_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<Vec<(String, String)>> {
+        self.execute(request::find_sticker(typ, uri, name)).await
+    }
+
+    /// List all files of a given type under given directory (identified by uri)
+    /// with a tag set to given value
+    pub async fn find_sticker_eq(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<Vec<String>> {
+        self.execute(request::find_sticker_eq(typ, uri, name, value)).await
+    }
+    // }}}
+
+    // Idle {{{
+    /// Wait for events from a set of subsystems and return list of affected subsystems
+    ///
+    /// If empty subsystems slice is given, wait for all event from any subsystem.
+    /// If the returned future is dropped before completion, idle mode is interrupted
+    /// (and the events are lost) when the next command is sent.
+    pub async fn wait(&mut self, subsystems: &[Subsystem]) -> Result<Vec<Subsystem>> {
+        let request = request::idle(subsystems)?;
+        request.check(self.version)?;
+        self.send(request.encoded()).await?;
+        self.idling = true;
+        let response = self.read_response().await;
+        self.idling = false;
+        request.parse(&mut response?)
+    }
+
+    /// Listen for events from a set of subsystems as a stream
+    ///
+    /// The stream yields changed subsystems one by one, entering idle mode again
+    /// after each batch, and ends after the first error. The client is borrowed until
+    /// the stream is dropped, idle mode is interrupted when the next command is sent.
+    pub fn idle(&mut self, subsystems: &[Subsystem]) -> IdleStream<'_, S>
+    where
+        S: Send,
+    {
+        IdleStream {
+            subsystems: subsystems.to_vec(),
+            events: VecDeque::new(),
+            state: IdleState::Ready(self),
+        }
+    }
+    // }}}
+}
+
+// Helper methods {{{
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncClient<S> {
+    /// Send a request and parse its response
+    async fn execute<T>(&mut self, request: Result<Request<T>>) -> Result<T> {
+        let request = request?;
+        request.check(self.version)?;
+        self.send(request.encoded()).await?;
+        let mut response = self.read_reply().await?;
+        request.parse(&mut response)
+    }
+
+    /// Write commands, after reading responses left by an interrupted idle mode or dropped futures
+    async fn send(&mut self, data: &[u8]) -> Result<()> {
+        if self.idling {
+            // Idle mode was left by a dropped stream or future, interrupt it first
+            self.write_raw(b"noidle\n").await?;
+            self.read_response().await?;
+            self.idling = false;
+        }
+        while self.skip > 0 {
+            self.read_response().await?;
+            self.skip -= 1;
+        }
+        self.write_raw(data).await
+    }
+
+    /// Read a response to a sent command, which is skipped later if the future is dropped
+    async fn read_reply(&mut self) -> Result<Replay> {
+        self.skip += 1;
+        let response = self.read_response().await?;
+        self.skip -= 1;
+        Ok(response)
+    }
+
+    /// Read a complete response (up to `OK` or `ACK`), to be parsed later
+    ///
+    /// Broken lines are kept as errors to be reported by the parser.
+    /// Events are kept in the client until the end of response, so reading
+    /// can be resumed if the future is dropped.
+    async fn read_response(&mut self) -> Result<Replay> {
+        loop {
+            let event = match self.read_event().await {
+                Err(e) if !e.is_recoverable() => return Err(e),
+                event => event,
+            };
+            let end = matches!(event, Ok(Event::Ok) | Ok(Event::Ack(_)));
+            self.partial.push(event);
+            if end {
+                return Ok(Replay::new(mem::take(&mut self.partial), self.limits()));
+            }
+        }
+    }
+
+    async fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        self.socket.write_all(data).await?;
+        self.socket.flush().await?;
+        Ok(())
+    }
+
+    /// Read next event, waiting until it's decoded
+    async fn read_event(&mut self) -> Result<Event> {
+        let mut buf = [0; 8192];
+        loop {
+            if let Some(event) = self.decoder.decode()? {
+                return Ok(event);
+            }
+            match self.socket.read(&mut buf).await? {
+                0 => return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))),
+                n => self.decoder.feed(&buf[..n]),
+            }
+        }
+    }
+}
+// }}}
+
+// }}}
+
+// AsyncCommandList {{{
+
+/// Asynchronous command list builder, see [`CommandList`](../command_list/struct.CommandList.html)
+pub struct AsyncCommandList<'a, S: AsyncRead + AsyncWrite + Unpin> {
+    client: &'a mut AsyncClient<S>,
+    commands: Commands,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Deref for AsyncCommandList<'a, S> {
+    type Target = Commands;
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> DerefMut for AsyncCommandList<'a, S> {
+    fn deref_mut(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncCommandList<'a, S> {
+    /// Send all queued commands to the server and read their replies
+    ///
+    /// See [`CommandList::run`](../command_list/struct.CommandList.html#method.run).
+    pub async fn run(mut self) -> Result<Replies> {
+        let buf = command_list::wrap(self.commands.encoded()?);
+        self.client.send(&buf).await?;
+        let mut response = self.client.read_reply().await?;
+        command_list::read_replies(&mut response)
+    }
+}
+// }}}
+
+// AsyncPipeline {{{
+
+/// Asynchronous pipeline builder, see [`Pipeline`](../pipeline/struct.Pipeline.html)
+pub struct AsyncPipeline<'a, S: AsyncRead + AsyncWrite + Unpin> {
+    client: &'a mut AsyncClient<S>,
+    commands: Commands,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Deref for AsyncPipeline<'a, S> {
+    type Target = Commands;
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> DerefMut for AsyncPipeline<'a, S> {
+    fn deref_mut(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncPipeline<'a, S> {
+    /// Write all queued commands to the server without waiting for replies
+    pub async fn send(mut self) -> Result<AsyncPending<'a, S>> {
+        self.client.send(self.commands.encoded()?).await?;
+        Ok(AsyncPending {
+            client: self.client,
+            count: self.commands.len(),
+            replies: Vec::new(),
+            broken: false,
+        })
+    }
+}
+
+/// Replies to a sent asynchronous pipeline, read from the connection on demand
+///
+/// If dropped before all replies are taken, the rest of replies is read and discarded
+/// before the next command.
+pub struct AsyncPending<'a, S: AsyncRead + AsyncWrite + Unpin> {
+    client: &'a mut AsyncClient<S>,
+    count: usize,
+    replies: Vec<Option<CommandReply>>,
+    broken: bool,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncPending<'a, S> {
+    /// Take result of a command identified by its ticket, reading replies up to it if needed
+    pub async fn take<T>(&mut self, ticket: Ticket<T>) -> Result<T> {
+        while self.replies.len() <= ticket.index() && self.replies.len() < self.count {
+            let reply = self.read_reply().await?;
+            self.replies.push(Some(reply));
+        }
+        match self.replies.get_mut(ticket.index()).and_then(Option::take) {
+            Some(Ok(pairs)) => ticket.parse(pairs, self.client.limits()),
+            Some(Err(e)) => Err(e),
+            None => Err(Error::Proto(ProtoError::Skipped)),
+        }
+    }
+
+    /// Read all remaining replies, returning the first error of replies not taken yet, if any
+    ///
+    /// See [`Pending::finish`](../pipeline/struct.Pending.html#method.finish).
+    pub async fn finish(mut self) -> Result<()> {
+        while self.replies.len() < self.count {
+            let reply = self.read_reply().await?;
+            self.replies.push(Some(reply));
+        }
+        match self.replies.iter_mut().find_map(|r| r.take().and_then(Result::err)) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    async fn read_reply(&mut self) -> Result<CommandReply> {
+        if self.broken {
+            return Err(Error::Proto(ProtoError::Skipped));
+        }
+        let reply = match self.client.read_response().await {
+            Ok(mut response) => pipeline::read_reply(&mut response),
+            Err(e) => Err(e),
+        };
+        // replies are out of sync with commands from now on
+        self.broken = reply.is_err();
+        reply
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> Drop for AsyncPending<'a, S> {
+    fn drop(&mut self) {
+        if !self.broken {
+            self.client.skip += self.count - self.replies.len();
+        }
+    }
+}
+// }}}
+
+// IdleStream {{{
+
+type IdleFuture<'a, S> = Pin<Box<dyn Future<Output = (Result<Vec<Subsystem>>, &'a mut AsyncClient<S>)> + Send + 'a>>;
+
+enum IdleState<'a, S: AsyncRead + AsyncWrite + Unpin> {
+    Ready(&'a mut AsyncClient<S>),
+    Waiting(IdleFuture<'a, S>),
+    Done,
+}
+
+/// Stream of events from subsystems, returned by [`AsyncClient::idle`](struct.AsyncClient.html#method.idle)
+pub struct IdleStream<'a, S: AsyncRead + AsyncWrite + Unpin> {
+    subsystems: Vec<Subsystem>,
+    events: VecDeque<Subsystem>,
+    state: IdleState<'a, S>,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> fmt::Debug for IdleStream<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdleStream")
+            .field("subsystems", &self.subsystems)
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin + Send> IdleStream<'a, S> {
+    /// Wait for the next event
+    ///
+    /// This is the same as `StreamExt::next()`, for those who don't use `futures` crate.
+    pub async fn next_event(&mut self) -> Option<Result<Subsystem>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin + Send> Stream for IdleStream<'a, S> {
+    type Item = Result<Subsystem>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Subsystem>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.events.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            match std::mem::replace(&mut this.state, IdleState::Done) {
+                IdleState::Ready(client) => {
+                    let subsystems = this.subsystems.clone();
+                    this.state = IdleState::Waiting(Box::pin(async move {
+                        let result = client.wait(&subsystems).await;
+                        (result, client)
+                    }));
+                }
+                IdleState::Waiting(mut future) => match future.as_mut().poll(cx) {
+                    Poll::Pending => {
+                        this.state = IdleState::Waiting(future);
+                        return Poll::Pending;
+                    }
+                    Poll::Ready((Ok(events), client)) => {
+                        this.events.extend(events);
+                        this.state = IdleState::Ready(client);
+                    }
+                    Poll::Ready((Err(e), _)) => return Poll::Ready(Some(Err(e))),
+                },
+                IdleState::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+// }}}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncClient;
+    use crate::connect::ConnectOptions;
+    use crate::error::{Error, ParseError, ProtoError};
+    use crate::idle::Subsystem;
+    use crate::search::{Expression, FilterQuery, Query, Term};
+
+    use std::future::{poll_fn, Future};
+    use std::task::Poll;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// Client connected to a fake server, which has already sent all of its responses
+    async fn client(responses: &str) -> (AsyncClient<DuplexStream>, DuplexStream) {
+        let (client, mut server) = duplex(1 << 16);
+        server.write_all(b"OK MPD 0.23.0\n").await.unwrap();
+        server.write_all(responses.as_bytes()).await.unwrap();
+        (AsyncClient::new(client).await.unwrap(), server)
+    }
+
+    async fn written(client: AsyncClient<DuplexStream>, mut server: DuplexStream) -> String {
+        drop(client);
+        let mut buf = String::new();
+        server.read_to_string(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn commands() {
+        let (mut conn, server) = client(concat!(
+            "volume: 50\nstate: play\nsong: 1\nsongid: 2\nreplay_gain_mode: off\nOK\n",
+            "file: a.flac\nTitle: A\nfile: b.flac\nOK\n",
+            "Id: 7\nOK\n",
+            "sticker: rating=5\nsticker: bad\nOK\n",
+            "Artist: X\nOK\n",
+            "ACK [50@0] {add} no such file\n",
+            "size: 5\nbinary: 3\nabc\nOK\nsize: 5\nbinary: 2\nde\nOK\n",
+        ))
+        .await;
+
+        let status = conn.status().await.unwrap();
+        assert_eq!(status.volume, 50);
+        let songs = conn.find(&Query::from(Expression::eq(Term::Any, "A")), None, None).await.unwrap();
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].title.as_deref(), Some("A"));
+        assert_eq!(conn.push("a.flac").await.unwrap(), 7);
+        assert!(matches!(conn.stickers("song", "a.flac").await, Err(Error::Proto(ProtoError::BadSticker))));
+        let artists = conn.list(&Term::Tag("Artist"), &Query::from(FilterQuery::new()), None).await;
+        assert_eq!(artists.unwrap(), vec!["X"]);
+        assert!(matches!(conn.add("x.flac").await, Err(Error::Server(_))));
+        assert_eq!(conn.albumart("a.flac").await.unwrap().unwrap(), b"abcde");
+
+        assert_eq!(
+            written(conn, server).await,
+            concat!(
+                "command_list_begin\nstatus\nreplay_gain_status\ncommand_list_end\n",
+                "find \"(any == \\\"A\\\")\"\n",
+                "addid \"a.flac\"\n",
+                "sticker list \"song\" \"a.flac\"\n",
+                "list \"Artist\"\n",
+                "add \"x.flac\"\n",
+                "albumart \"a.flac\" \"0\"\nalbumart \"a.flac\" \"3\"\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn idle() {
+        let (mut conn, mut server) = client("changed: player\nchanged: mixer\nOK\nchanged: options\nOK\n").await;
+        {
+            let mut events = conn.idle(&[]);
+            assert_eq!(events.next_event().await.unwrap().unwrap(), Subsystem::Player);
+            assert_eq!(events.next_event().await.unwrap().unwrap(), Subsystem::Mixer);
+            assert_eq!(events.next_event().await.unwrap().unwrap(), Subsystem::Options);
+        }
+        {
+            // Drop the stream while it's waiting for events
+            let mut events = conn.idle(&[Subsystem::Player]);
+            let next = events.next_event();
+            tokio::pin!(next);
+            assert!(poll_fn(|cx| Poll::Ready(next.as_mut().poll(cx).is_pending())).await);
+        }
+        server.write_all(b"OK\nOK\n").await.unwrap();
+        conn.ping().await.unwrap();
+
+        assert_eq!(written(conn, server).await, "idle\nidle\nidle \"player\"\nnoidle\nping\n");
+    }
+
+    #[tokio::test]
+    async fn idle_unknown() {
+        let (mut conn, server) = client("changed: neighbor\nchanged: player\nOK\n").await;
+        {
+            let mut events = conn.idle(&[]);
+            assert_eq!(events.next_event().await.unwrap().unwrap(), Subsystem::Player);
+        }
+        assert_eq!(written(conn, server).await, "idle\n");
+    }
+
+    #[tokio::test]
+    async fn command_list_and_pipeline() {
+        let (mut conn, server) = client(concat!(
+            "file: a.flac\nlist_OK\nId: 7\nlist_OK\nACK [50@2] {add} no such file\n",
+            "volume: 50\nOK\nACK [50@0] {add} no such file\nId: 8\nOK\n",
+            "Id: 9\nOK\nOK\nOK\n",
+        ))
+        .await;
+
+        let mut list = conn.command_list();
+        let queue = list.queue();
+        let id = list.push("b.flac");
+        let add = list.add("x.flac");
+        let status = list.status();
+        let mut replies = list.run().await.unwrap();
+        assert_eq!(replies.take(queue).unwrap().len(), 1);
+        assert_eq!(replies.take(id).unwrap(), 7);
+        assert!(matches!(replies.take(add), Err(Error::Server(_))));
+        assert!(matches!(replies.take(status), Err(Error::Proto(ProtoError::Skipped))));
+
+        let mut pipeline = conn.pipeline();
+        let status = pipeline.status();
+        let add = pipeline.add("x.flac");
+        let id = pipeline.push("b.flac");
+        let mut pending = pipeline.send().await.unwrap();
+        assert_eq!(pending.take(status).await.unwrap().volume, 50);
+        assert_eq!(pending.take(id).await.unwrap(), 8);
+        drop(add);
+        pending.finish().await.unwrap_err();
+
+        // Replies to a dropped pipeline are skipped before the next command
+        let mut pipeline = conn.pipeline();
+        pipeline.push("c.flac");
+        pipeline.deleteid(9);
+        drop(pipeline.send().await.unwrap());
+        conn.ping().await.unwrap();
+
+        assert_eq!(
+            written(conn, server).await,
+            concat!(
+                "command_list_ok_begin\nplaylistinfo\naddid \"b.flac\"\nadd \"x.flac\"\nstatus\ncommand_list_end\n",
+                "status\nadd \"x.flac\"\naddid \"b.flac\"\n",
+                "addid \"c.flac\"\ndeleteid \"9\"\nping\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn lossy_utf8() {
+        let mut responses = b"file: a\xff.flac\nOK\n".to_vec();
+        responses.extend_from_slice(b"file: b\xff.flac\nOK\nOK\n");
+        let (client, mut server) = duplex(1 << 16);
+        server.write_all(b"OK MPD 0.23.0\n").await.unwrap();
+        server.write_all(&responses).await.unwrap();
+        let mut conn = AsyncClient::new(client).await.unwrap();
+
+        assert!(matches!(conn.queue().await, Err(Error::Parse(ParseError::BadUtf8(_)))));
+        conn.set_lossy_utf8(true);
+        assert_eq!(conn.queue().await.unwrap()[0].file, "b\u{fffd}.flac");
+        assert_eq!(conn.take_lossy_lines()[0].raw, b"file: b\xff.flac");
+        conn.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn options_and_supports() {
+        let (client, mut server) = duplex(1 << 16);
+        server.write_all(b"OK MPD 0.23.0\nOK\nOK\nOK\nOK\n").await.unwrap();
+        server
+            .write_all(b"command: albumart\ncommand: sticker\ncommand: password\nOK\ncommand: sticker\nOK\n")
+            .await
+            .unwrap();
+        let mut options = ConnectOptions::new();
+        options.password("secret").binary_limit(65536).tagtypes(&["Artist"]);
+        let mut conn = AsyncClient::with_options(client, &options).await.unwrap();
+        assert_eq!(conn.binary_limit(), 65536);
+
+        assert!(conn.supports("albumart").await.unwrap());
+        assert!(!conn.supports("sticker get").await.unwrap());
+        assert!(!conn.supports("protocol").await.unwrap());
+
+        assert_eq!(
+            written(conn, server).await,
+            concat!("password \"secret\"\nbinarylimit \"65536\"\ntagtypes clear\ntagtypes enable \"Artist\"\n", "commands\nnotcommands\n",)
+        );
+    }
+}
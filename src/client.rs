@@ -4,27 +4,38 @@
 //!
 //! [proto]: http://www.musicpd.org/doc/protocol/
 
-use bufstream::BufStream;
-
-use crate::error::{Error, ProtoError, Result};
-use crate::lsinfo::LsInfoResponse;
+use crate::codec::{Decoder, Event};
+use crate::command_list::CommandList;
+use crate::connect::ConnectOptions;
+use crate::error::{Error, ErrorCode, ProtoError, Result};
+use crate::list::ListGroup;
+use crate::lsinfo::{LsInfoIter, LsInfoResponse};
 use crate::message::{Channel, Message};
 use crate::mount::{Mount, Neighbor};
 use crate::output::Output;
+use crate::picture::{Picture, PictureInfo};
+use crate::pipeline::Pipeline;
 use crate::playlist::Playlist;
 use crate::plugin::Plugin;
 use crate::proto::*;
-use crate::search::{Query, Term, Window};
-use crate::song::{Range, Song};
-use crate::stats::Stats;
+use crate::reply::Response;
+use crate::request::{self, Request, Transfer};
+use crate::search::{Query, Sort, Term, Window};
+use crate::song::{Position, Range, Song};
+use crate::stats::{Count, Stats};
 use crate::status::{ReplayGain, Status};
-use crate::sticker::Sticker;
-use crate::version::Version;
+use crate::stream::{ReadTimeout, Stream};
+use crate::version::{check_version, Version};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
-use std::io::{BufRead, Lines, Read, Write};
+use std::io::{self, Read, Write};
 use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 // Client {{{
 
@@ -34,17 +45,53 @@ pub struct Client<S = TcpStream>
 where
     S: Read + Write,
 {
-    socket: BufStream<S>,
+    socket: S,
+    decoder: Decoder,
     /// MPD version
     pub version: Version,
+    binary_limit: usize,
+    max_binary_limit: Option<usize>,
+    commands: Option<HashSet<String>>,
+    last_write: Instant,
+    read_timeout: bool,
+    pub(crate) idling: bool,
+}
+
+/// Caps on server responses, protecting the client from misbehaving servers
+///
+/// A response exceeding any of them fails with `ProtoError::TooLarge`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// maximum length of a response line in bytes (1 MiB by default)
+    pub line_length: usize,
+    /// maximum number of records (e.g. songs) in a response (unlimited by default)
+    pub records: usize,
+    /// maximum size of a binary object (e.g. a picture) in bytes (64 MiB by default)
+    pub binary_size: usize,
 }
 
-impl Default for Client<TcpStream> {
-    fn default() -> Client<TcpStream> {
-        Client::<TcpStream>::connect("127.0.0.1:6600").unwrap()
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            line_length: 1 << 20,
+            records: usize::MAX,
+            binary_size: 64 << 20,
+        }
     }
 }
 
+/// Response line which was not valid UTF-8, and was decoded lossily
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossyLine {
+    /// decoded line, with invalid sequences replaced by `U+FFFD REPLACEMENT CHARACTER`
+    pub line: String,
+    /// original line bytes (without trailing newline)
+    pub raw: Vec<u8>,
+}
+
+/// Default maximum size of a binary response chunk, as set by MPD for new connections
+pub(crate) const DEFAULT_BINARY_LIMIT: usize = 8192;
+
 impl Client<TcpStream> {
     /// Connect client to some IP address
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Client<TcpStream>> {
@@ -52,296 +99,455 @@ impl Client<TcpStream> {
     }
 }
 
+#[cfg(unix)]
+impl Client<UnixStream> {
+    /// Connect client to a Unix domain socket
+    ///
+    /// Path starting with `@` (like `@mpd`) denotes a Linux abstract socket.
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<Client<UnixStream>> {
+        ConnectOptions::new().connect_unix(path)
+    }
+}
+
+impl Client<Stream> {
+    /// Connect client as configured by environment, following libmpdclient rules
+    ///
+    /// `MPD_HOST` is a host name, a socket path (starting with `/`) or an abstract socket name
+    /// (starting with `@`). A host name or an abstract socket name may be prefixed with `password@`. `MPD_PORT` is a TCP port (6600 by default).
+    /// Without `MPD_HOST` it tries `$XDG_RUNTIME_DIR/mpd/socket`, then `localhost`.
+    ///
+    /// If a password is given, it's sent to the server right after connection.
+    pub fn from_env() -> Result<Client<Stream>> {
+        ConnectOptions::new().from_env()
+    }
+}
+
 impl<S: Read + Write> Client<S> {
     // Constructors {{{
     /// Create client from some arbitrary pre-connected socket
     pub fn new(socket: S) -> Result<Client<S>> {
-        let mut socket = BufStream::new(socket);
+        Client::with_options(socket, &ConnectOptions::new())
+    }
 
-        let mut banner = String::new();
-        socket.read_line(&mut banner)?;
+    /// Create client from some arbitrary pre-connected socket, and set it up with given options
+    ///
+    /// Timeouts are not applied here, as they are socket specific,
+    /// use `ConnectOptions::connect()` and alike methods for them.
+    pub fn with_options(socket: S, options: &ConnectOptions) -> Result<Client<S>> {
+        let mut client = Client {
+            socket,
+            decoder: Decoder::new(),
+            version: Version(0, 0, 0),
+            binary_limit: DEFAULT_BINARY_LIMIT,
+            max_binary_limit: None,
+            commands: None,
+            last_write: Instant::now(),
+            read_timeout: options.has_read_timeout(),
+            idling: false,
+        };
+
+        client.version = match client.read_event()? {
+            Event::Greeting(version) => version,
+            _ => return Err(Error::Proto(ProtoError::BadBanner)),
+        };
+
+        for request in options.setup() {
+            client.execute(request)?;
+        }
+        if let Some(size) = options.initial_binary_limit() {
+            client.binary_limit = size;
+        }
+        Ok(client)
+    }
+    // }}}
 
-        if !banner.starts_with("OK MPD ") {
-            return Err(From::from(ProtoError::BadBanner));
+    /// Start a command list, to send several commands in a single round trip
+    pub fn command_list(&mut self) -> CommandList<'_, S> {
+        CommandList::new(self)
+    }
+
+    /// Start a pipeline, to send several commands at once and read their replies later
+    pub fn pipeline(&mut self) -> Pipeline<'_, S> {
+        Pipeline::new(self)
+    }
+
+    /// Run arbitrary command and read its response
+    ///
+    /// This is an escape hatch for commands which have no dedicated methods yet.
+    /// The connection is left in a consistent state, even if the server responds with `ACK`.
+    pub fn raw_command(&mut self, command: &str, arguments: &[&str]) -> Result<Response> {
+        self.execute(request::raw(command, arguments))
+    }
+
+    /// Set how to handle response lines which are not valid UTF-8 (like paths and tags in legacy encodings)
+    ///
+    /// By default such a line is skipped and reported as a `BadUtf8` parse error, which fails the command
+    /// (the rest of its response is still read, so the connection stays usable).
+    /// In lossy mode invalid sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`,
+    /// and original lines are kept to be reported by [`take_lossy_lines`](#method.take_lossy_lines).
+    pub fn set_lossy_utf8(&mut self, lossy: bool) {
+        self.decoder.set_lossy_utf8(lossy);
+    }
+
+    /// Take all lines decoded lossily since the last call
+    ///
+    /// Lines are kept in the order they were received, so e.g. a `file: ...` line identifies
+    /// a song with a broken path, and a tag line right after it identifies its broken tag.
+    pub fn take_lossy_lines(&mut self) -> Vec<LossyLine> {
+        self.decoder.take_lossy_lines()
+    }
+
+    /// Get the underlying socket
+    pub(crate) fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    /// Wait until some response data is available, returns `false` on timeout
+    ///
+    /// A zero timeout only checks data which is already received.
+    pub(crate) fn poll_read(&mut self, timeout: Duration) -> Result<bool>
+    where
+        S: ReadTimeout,
+    {
+        if self.decoder.buffered() > 0 {
+            return Ok(true);
+        }
+        if timeout.is_zero() {
+            return Ok(false);
         }
 
-        let version = banner[7..].trim().parse::<Version>()?;
+        let saved = self.socket.read_timeout()?;
+        self.socket.set_read_timeout(Some(timeout))?;
+        let mut buf = [0; 8192];
+        let result = self.socket.read(&mut buf);
+        self.socket.set_read_timeout(saved)?;
+        match result {
+            Ok(0) => Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))),
+            Ok(n) => {
+                self.decoder.feed(&buf[..n]);
+                Ok(true)
+            }
+            Err(e) => match Error::timed_out(e) {
+                Error::Timeout => Ok(false),
+                e => Err(e),
+            },
+        }
+    }
 
-        Ok(Client { socket, version })
+    /// Copy client side settings (limits, lossy mode etc.) from another client
+    pub(crate) fn copy_settings(&mut self, other: &Client<S>) {
+        self.max_binary_limit = other.max_binary_limit;
+        self.decoder.set_lossy_utf8(other.decoder.lossy_utf8());
+        self.decoder.set_limits(other.decoder.limits());
+    }
+
+    /// Set caps on server responses
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Get current caps on server responses
+    pub fn limits(&self) -> Limits {
+        self.decoder.limits()
     }
-    // }}}
 
     // Playback options & status {{{
     /// Get MPD status
     pub fn status(&mut self) -> Result<Status> {
-        self.run_command("command_list_begin", ())
-            .and_then(|_| self.run_command("status", ()))
-            .and_then(|_| self.run_command("replay_gain_status", ()))
-            .and_then(|_| self.run_command("command_list_end", ()))
-            .and_then(|_| self.read_struct())
+        self.execute(request::full_status())
     }
 
     /// Get MPD playing statistics
     pub fn stats(&mut self) -> Result<Stats> {
-        self.run_command("stats", ()).and_then(|_| self.read_struct())
+        self.execute(request::stats())
     }
 
     /// Clear error state
     pub fn clearerror(&mut self) -> Result<()> {
-        self.run_command("clearerror", ()).and_then(|_| self.expect_ok())
+        self.execute(request::clearerror())
     }
 
     /// Set volume
     pub fn volume(&mut self, volume: i8) -> Result<()> {
-        self.run_command("setvol", volume).and_then(|_| self.expect_ok())
+        self.execute(request::volume(volume))
     }
 
     /// Set repeat state
     pub fn repeat(&mut self, value: bool) -> Result<()> {
-        self.run_command("repeat", value as u8).and_then(|_| self.expect_ok())
+        self.execute(request::repeat(value))
     }
 
     /// Set random state
     pub fn random(&mut self, value: bool) -> Result<()> {
-        self.run_command("random", value as u8).and_then(|_| self.expect_ok())
+        self.execute(request::random(value))
     }
 
     /// Set single state
     pub fn single(&mut self, value: bool) -> Result<()> {
-        self.run_command("single", value as u8).and_then(|_| self.expect_ok())
+        self.execute(request::single(value))
     }
 
     /// Set consume state
     pub fn consume(&mut self, value: bool) -> Result<()> {
-        self.run_command("consume", value as u8).and_then(|_| self.expect_ok())
+        self.execute(request::consume(value))
     }
 
     /// Set crossfade time in seconds
     pub fn crossfade(&mut self, value: u32) -> Result<()> {
-        self.run_command("crossfade", value).and_then(|_| self.expect_ok())
+        self.execute(request::crossfade(value))
     }
 
     /// Set mixramp level in dB
     pub fn mixrampdb(&mut self, value: f32) -> Result<()> {
-        self.run_command("mixrampdb", value).and_then(|_| self.expect_ok())
+        self.execute(request::mixrampdb(value))
     }
 
     /// Set mixramp delay in seconds
     pub fn mixrampdelay(&mut self, value: u32) -> Result<()> {
-        self.run_command("mixrampdelay", value).and_then(|_| self.expect_ok())
+        self.execute(request::mixrampdelay(value))
     }
 
     /// Set replay gain mode
     pub fn replaygain(&mut self, gain: ReplayGain) -> Result<()> {
-        self.run_command("replay_gain_mode", gain).and_then(|_| self.expect_ok())
+        self.execute(request::replaygain(gain))
     }
     // }}}
 
     // Playback control {{{
     /// Start playback
     pub fn play(&mut self) -> Result<()> {
-        self.run_command("play", ()).and_then(|_| self.expect_ok())
+        self.execute(request::play())
     }
 
     /// Start playback from given position in a queue
     pub fn play_from_position(&mut self, place: u32) -> Result<()> {
-        self.run_command("play", place).and_then(|_| self.expect_ok())
+        self.execute(request::play_from_position(place))
     }
 
     /// Start playback from given id in a queue
     pub fn play_from_id(&mut self, place: u32) -> Result<()> {
-        self.run_command("playid", place).and_then(|_| self.expect_ok())
+        self.execute(request::play_from_id(place))
     }
 
     /// Switch to a next song in queue
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::should_implement_trait))]
     pub fn next(&mut self) -> Result<()> {
-        self.run_command("next", ()).and_then(|_| self.expect_ok())
+        self.execute(request::next())
     }
 
     /// Switch to a previous song in queue
     pub fn prev(&mut self) -> Result<()> {
-        self.run_command("previous", ()).and_then(|_| self.expect_ok())
+        self.execute(request::prev())
     }
 
     /// Stop playback
     pub fn stop(&mut self) -> Result<()> {
-        self.run_command("stop", ()).and_then(|_| self.expect_ok())
+        self.execute(request::stop())
     }
 
     /// Toggle pause state
     pub fn toggle_pause(&mut self) -> Result<()> {
-        self.run_command("pause", ()).and_then(|_| self.expect_ok())
+        self.execute(request::toggle_pause())
     }
 
     /// Set pause state
     pub fn pause(&mut self, value: bool) -> Result<()> {
-        self.run_command("pause", value as u8).and_then(|_| self.expect_ok())
+        self.execute(request::pause(value))
     }
 
     /// Seek to a given place (in seconds) in a song identified by position
     pub fn seek(&mut self, place: u32, pos: u32) -> Result<()> {
-        self.run_command("seek", (place, pos)).and_then(|_| self.expect_ok())
+        self.execute(request::seek(place, pos))
     }
 
     /// Seek to a given place (in seconds) in a song identified by id
     pub fn seek_id(&mut self, place: u32, pos: u32) -> Result<()> {
-        self.run_command("seekid", (place, pos)).and_then(|_| self.expect_ok())
+        self.execute(request::seek_id(place, pos))
     }
 
     /// Seek to a given place (in seconds) in the current song
     pub fn rewind(&mut self, pos: u32) -> Result<()> {
-        self.run_command("seekcur", pos).and_then(|_| self.expect_ok())
+        self.execute(request::rewind(pos))
     }
     // }}}
 
     // Queue control {{{
     /// List given song or range of songs in a play queue
     pub fn playlistinfo<T: Into<Range>>(&mut self, pos: T) -> Result<Vec<Song>> {
-        self.run_command("playlistinfo", pos.into()).and_then(|_| self.read_structs("file"))
+        self.execute(request::playlistinfo(pos.into()))
     }
 
     /// List given song in a play queue
     pub fn playlistid(&mut self, pos: u32) -> Result<Song> {
-        self.run_command("playlistid", pos).and_then(|_| self.read_struct())
+        self.execute(request::playlistid(pos))
     }
 
     /// List all songs in a play queue
     pub fn queue(&mut self) -> Result<Vec<Song>> {
-        self.run_command("playlistinfo", ()).and_then(|_| self.read_structs("file"))
+        self.execute(request::queue())
     }
 
     /// Get current playing song
     pub fn currentsong(&mut self) -> Result<Option<Song>> {
-        self.run_command("currentsong", ())
-            .and_then(|_| self.read_struct::<Song>())
-            .map(|s| if s.place.is_none() { None } else { Some(s) })
+        self.execute(request::currentsong())
     }
 
     /// Clear current queue
     pub fn clear(&mut self) -> Result<()> {
-        self.run_command("clear", ()).and_then(|_| self.expect_ok())
+        self.execute(request::clear())
     }
 
     /// List all changes in a queue since given version
     pub fn changes(&mut self, version: u32) -> Result<Vec<Song>> {
-        self.run_command("plchanges", version).and_then(|_| self.read_structs("file"))
+        self.execute(request::changes(version))
     }
 
     /// Append all songs in path (directories add recursively) to the playlist
     pub fn add(&mut self, path: &str) -> Result<()> {
-        self.run_command("add", path).and_then(|_| self.expect_ok())
+        self.execute(request::add(path))
     }
 
     /// Append a song into a queue
     pub fn push(&mut self, path: &str) -> Result<u32> {
-        self.run_command("addid", path).and_then(|_| self.read_field("Id"))
+        self.execute(request::push(path))
     }
 
     /// Insert a song into a given position in a queue
     pub fn insert(&mut self, path: &str, pos: usize) -> Result<usize> {
-        self.run_command("addid", (path, pos)).and_then(|_| self.read_field("Id"))
+        self.execute(request::insert(path, pos)).map(|id| id as usize)
     }
 
     /// Delete several songs (in a range) from a queue
     pub fn delete<T: Into<Range>>(&mut self, pos: T) -> Result<()> {
-        self.run_command("delete", pos.into()).and_then(|_| self.expect_ok())
+        self.execute(request::delete(pos.into()))
     }
 
     /// Delete a song from a queue
     pub fn deleteid(&mut self, pos: u32) -> Result<()> {
-        self.run_command("deleteid", pos).and_then(|_| self.expect_ok())
+        self.execute(request::deleteid(pos))
     }
 
     /// Move a song (at a some position) or several songs (in a range) to other position in queue
     pub fn move_range<T: Into<Range>>(&mut self, from: T, to: usize) -> Result<()> {
-        self.run_command("move", (from.into(), to)).and_then(|_| self.expect_ok())
+        self.execute(request::move_range(from.into(), to))
     }
 
     /// Move a song (at a some position) or several songs (in a range) to other position in queue
     pub fn moveid(&mut self, from: u32, to: usize) -> Result<()> {
-        self.run_command("moveid", (from, to)).and_then(|_| self.expect_ok())
+        self.execute(request::moveid(from, to))
     }
 
     /// Swap two songs identified by queue position in a queue
     pub fn swap(&mut self, one: u32, two: u32) -> Result<()> {
-        self.run_command("swap", (one, two)).and_then(|_| self.expect_ok())
+        self.execute(request::swap(one, two))
     }
 
     /// Swap two songs identified by id in a queue
     pub fn swapid(&mut self, one: u32, two: u32) -> Result<()> {
-        self.run_command("swapid", (one, two)).and_then(|_| self.expect_ok())
+        self.execute(request::swapid(one, two))
     }
 
     /// Shuffle queue in a given range (use `..` to shuffle full queue)
     pub fn shuffle<T: Into<Range>>(&mut self, range: T) -> Result<()> {
-        self.run_command("shuffle", range.into()).and_then(|_| self.expect_ok())
+        self.execute(request::shuffle(range.into()))
     }
 
     /// Set song priority in a queue
     pub fn prio<T: Into<Range>>(&mut self, pos: T, prio: u8) -> Result<()> {
-        self.run_command("prio", (prio, pos.into())).and_then(|_| self.expect_ok())
+        self.execute(request::prio(pos.into(), prio))
     }
 
     /// Set song priority in a queue
     pub fn prioid(&mut self, pos: u32, prio: u8) -> Result<()> {
-        self.run_command("prioid", (prio, pos)).and_then(|_| self.expect_ok())
+        self.execute(request::prioid(pos, prio))
     }
 
     /// Set song range (in seconds) to play
     ///
     /// Doesn't work for currently playing song.
     pub fn range<T: Into<Range>>(&mut self, song: u32, range: T) -> Result<()> {
-        self.run_command("rangeid", (song, range.into())).and_then(|_| self.expect_ok())
+        self.execute(request::range(song, range.into()))
     }
 
     /// Add tag to a song
     pub fn tag(&mut self, song: u32, tag: &str, value: &str) -> Result<()> {
-        self.run_command("addtagid", (song, tag, value)).and_then(|_| self.expect_ok())
+        self.execute(request::tag(song, tag, value))
     }
 
     /// Delete tag from a song
     pub fn untag(&mut self, song: u32, tag: &str) -> Result<()> {
-        self.run_command("cleartagid", (song, tag)).and_then(|_| self.expect_ok())
+        self.execute(request::untag(song, tag))
     }
     // }}}
 
     // Connection settings {{{
     /// Just pings MPD server, does nothing
     pub fn ping(&mut self) -> Result<()> {
-        self.run_command("ping", ()).and_then(|_| self.expect_ok())
+        self.execute(request::ping())
+    }
+
+    /// Send `ping` if nothing was sent to the server for at least a given period
+    ///
+    /// This keeps the connection from being closed by MPD's `connection_timeout`,
+    /// so the period should be well below it. Nothing is sent while waiting in idle mode
+    /// (MPD doesn't time out idling clients). Returns `true` if `ping` was sent.
+    ///
+    /// See [`KeepAlive`](../keepalive/struct.KeepAlive.html) to call it periodically from a thread.
+    pub fn keepalive(&mut self, period: Duration) -> Result<bool> {
+        if self.idling || self.last_write.elapsed() < period {
+            return Ok(false);
+        }
+        self.ping().map(|_| true)
     }
 
     /// Close MPD connection
     pub fn close(&mut self) -> Result<()> {
-        self.run_command("close", ()).and_then(|_| self.expect_ok())
+        self.execute(request::close())
     }
 
     /// Kill MPD server
     pub fn kill(&mut self) -> Result<()> {
-        self.run_command("kill", ()).and_then(|_| self.expect_ok())
+        self.execute(request::kill())
     }
 
     /// Login to MPD server with given password
     pub fn login(&mut self, password: &str) -> Result<()> {
-        self.run_command("password", password).and_then(|_| self.expect_ok())
+        // Permissions may change, so allowed commands should be fetched again
+        self.commands = None;
+        self.execute(request::login(password))
     }
 
     /// Set the maximum binary response size for the current connection to the specified number of bytes.
     pub fn binarylimit(&mut self, size: usize) -> Result<()> {
-        self.run_command("binarylimit", size).and_then(|_| self.expect_ok())
+        self.execute(request::binarylimit(size))?;
+        self.binary_limit = size;
+        Ok(())
+    }
+
+    /// Get the maximum binary response size negotiated for the current connection
+    pub fn binary_limit(&self) -> usize {
+        self.binary_limit
+    }
+
+    /// Allow binary transfers (like `albumart`) to temporarily raise binary limit up to the given size
+    ///
+    /// If a picture doesn't fit into a single chunk, the limit is raised for the rest of the transfer
+    /// (but not above the given size), and is restored once the transfer is finished.
+    /// Use `None` (the default) to always use the current limit.
+    pub fn set_max_binary_limit(&mut self, size: Option<usize>) {
+        self.max_binary_limit = size;
     }
     // }}}
 
     // Playlist methods {{{
     /// List all playlists
     pub fn playlists(&mut self) -> Result<Vec<Playlist>> {
-        self.run_command("listplaylists", ()).and_then(|_| self.read_structs("playlist"))
+        self.execute(request::playlists())
     }
 
     /// List all songs in a playlist
     pub fn playlist(&mut self, name: &str) -> Result<Vec<Song>> {
-        self.run_command("listplaylistinfo", name).and_then(|_| self.read_structs("file"))
+        self.execute(request::playlist(name))
     }
 
     /// Load playlist into queue
@@ -349,44 +555,44 @@ impl<S: Read + Write> Client<S> {
     /// You can give either full range (`..`) to load all songs in a playlist,
     /// or some partial range to load only part of playlist.
     pub fn load<T: Into<Range>>(&mut self, name: &str, range: T) -> Result<()> {
-        self.run_command("load", (name, range.into())).and_then(|_| self.expect_ok())
+        self.execute(request::load(name, range.into()))
     }
 
     /// Save current queue into playlist
     ///
     /// If playlist with given name doesn't exist, create new one.
     pub fn save(&mut self, name: &str) -> Result<()> {
-        self.run_command("save", name).and_then(|_| self.expect_ok())
+        self.execute(request::save(name))
     }
 
     /// Rename playlist
     pub fn pl_rename(&mut self, name: &str, newname: &str) -> Result<()> {
-        self.run_command("rename", (name, newname)).and_then(|_| self.expect_ok())
+        self.execute(request::pl_rename(name, newname))
     }
 
     /// Clear playlist
     pub fn pl_clear(&mut self, name: &str) -> Result<()> {
-        self.run_command("playlistclear", name).and_then(|_| self.expect_ok())
+        self.execute(request::pl_clear(name))
     }
 
     /// Delete playlist
     pub fn pl_remove(&mut self, name: &str) -> Result<()> {
-        self.run_command("rm", name).and_then(|_| self.expect_ok())
+        self.execute(request::pl_remove(name))
     }
 
     /// Add new songs to a playlist
     pub fn pl_push(&mut self, name: &str, path: &str) -> Result<()> {
-        self.run_command("playlistadd", (name, path)).and_then(|_| self.expect_ok())
+        self.execute(request::pl_push(name, path))
     }
 
     /// Delete a song at a given position in a playlist
     pub fn pl_delete(&mut self, name: &str, pos: u32) -> Result<()> {
-        self.run_command("playlistdelete", (name, pos)).and_then(|_| self.expect_ok())
+        self.execute(request::pl_delete(name, pos))
     }
 
     /// Move song in a playlist from one position into another
     pub fn pl_shift(&mut self, name: &str, from: u32, to: u32) -> Result<()> {
-        self.run_command("playlistmove", (name, from, to)).and_then(|_| self.expect_ok())
+        self.execute(request::pl_shift(name, from, to))
     }
     // }}}
 
@@ -394,87 +600,245 @@ impl<S: Read + Write> Client<S> {
     /// Run database rescan, i.e. remove non-existing files from DB
     /// as well as add new files to DB
     pub fn rescan(&mut self) -> Result<u32> {
-        self.run_command("rescan", ()).and_then(|_| self.read_field("updating_db"))
+        self.execute(request::rescan())
     }
 
     /// Run database update, i.e. remove non-existing files from DB
     pub fn update(&mut self) -> Result<u32> {
-        self.run_command("update", ()).and_then(|_| self.read_field("updating_db"))
+        self.execute(request::update())
     }
     // }}}
 
     // Database search {{{
-    // TODO: count tag needle [...] [group] [grouptag], find type what [...] [window start:end]
-    // TODO: search type what [...] [window start:end], searchadd type what [...]
-    // TODO: listallinfo [uri]
-    // TODO: list type [filtertype] [filterwhat] [...] [group] [grouptype] [...]
-    // TODO: searchaddpl name type what [...]
-
     /// List all songs/directories in directory
     pub fn listfiles(&mut self, song_path: &str) -> Result<Vec<(String, String)>> {
-        self.run_command("listfiles", song_path).and_then(|_| self.read_pairs().collect())
+        self.execute(request::listfiles(song_path))
     }
 
     /// Find songs matching Query conditions.
-    pub fn find<W>(&mut self, query: &Query, window: W) -> Result<Vec<Song>>
+    ///
+    /// Results are sorted according to `sort` (use `None` to keep server order)
+    /// and limited to `window` (use `None` to get all results).
+    pub fn find<'a, O, W>(&mut self, query: &Query, sort: O, window: W) -> Result<Vec<Song>>
     where
+        O: Into<Sort<'a>>,
         W: Into<Window>,
     {
-        self.find_generic("find", query, window.into())
+        self.find_generic("find", query, sort.into(), window.into())
+    }
+
+    /// Count songs matching Query conditions and their total playtime.
+    ///
+    /// If `group` is given, the result contains one entry per distinct value of that tag,
+    /// otherwise a single entry with `group` set to `None`.
+    pub fn count(&mut self, query: &Query, group: Option<Term>) -> Result<Vec<Count>> {
+        self.execute(request::count(query, group))
     }
 
     /// Find album art for file
-    pub fn albumart(&mut self, path: &str) -> Result<Vec<u8>> {
-        let mut buf = vec![];
+    ///
+    /// This is a cover file (like `cover.png`) in the song's directory.
+    /// Returns `None` if there is no such file.
+    pub fn albumart(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        self.albumart_to(path, 0, &mut data, |_, _| ()).map(|info| info.map(|_| data))
+    }
+
+    /// Stream album art for file into a writer, starting at a given offset
+    ///
+    /// The picture is transferred in chunks, and `progress` is called after each chunk
+    /// with the number of bytes received so far (including `offset`) and the total size.
+    /// A failed transfer can be resumed by calling it again with `offset` set to the number of bytes received.
+    /// Returns `None` if there is no album art.
+    pub fn albumart_to<W, F>(&mut self, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: Write,
+        F: FnMut(usize, usize),
+    {
+        match self.read_binary("albumart", path, offset, out, progress) {
+            Err(Error::Server(ref e)) if e.code == ErrorCode::NoExist => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Read picture embedded into song file
+    ///
+    /// Returns `None` if the song has no embedded picture.
+    pub fn readpicture(&mut self, path: &str) -> Result<Option<Picture>> {
+        let mut data = Vec::new();
+        self.readpicture_to(path, 0, &mut data, |_, _| ())
+            .map(|info| info.map(|info| Picture { data, mime: info.mime }))
+    }
+
+    /// Stream picture embedded into song file into a writer, starting at a given offset
+    ///
+    /// See [`albumart_to`](#method.albumart_to) for details.
+    /// Returns `None` if the song has no embedded picture.
+    pub fn readpicture_to<W, F>(&mut self, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: Write,
+        F: FnMut(usize, usize),
+    {
+        self.read_binary("readpicture", path, offset, out, progress)
+    }
+
+    /// Find cover art for a song, trying embedded picture first and falling back to album art
+    ///
+    /// Returns `None` if there is neither. Servers older than 0.22 don't support
+    /// `readpicture`, so only album art is looked for there.
+    pub fn cover_art(&mut self, path: &str) -> Result<Option<Picture>> {
+        match self.readpicture(path) {
+            Ok(Some(picture)) => Ok(Some(picture)),
+            Ok(None) | Err(Error::Unsupported { .. }) => self.albumart(path).map(|data| data.map(|data| Picture { data, mime: None })),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_binary<W, F>(&mut self, cmd: &str, path: &str, offset: usize, out: &mut W, progress: F) -> Result<Option<PictureInfo>>
+    where
+        W: Write,
+        F: FnMut(usize, usize),
+    {
+        let limit = self.binary_limit;
+        let result = self.read_binary_chunks(cmd, path, offset, out, progress);
+        if self.binary_limit != limit {
+            let restored = self.binarylimit(limit);
+            return result.and_then(|info| restored.map(|_| info));
+        }
+        result
+    }
+
+    fn read_binary_chunks<W, F>(
+        &mut self,
+        cmd: &str,
+        path: &str,
+        offset: usize,
+        out: &mut W,
+        mut progress: F,
+    ) -> Result<Option<PictureInfo>>
+    where
+        W: Write,
+        F: FnMut(usize, usize),
+    {
+        let mut transfer = Transfer::new(offset);
         loop {
-            self.run_command("albumart", (path, &*format!("{}", buf.len())))?;
-            let (_, size) = self.read_pair()?;
-            let (_, bytes) = self.read_pair()?;
-            let mut chunk = self.read_bytes(bytes.parse()?)?;
-            buf.append(&mut chunk);
-            // Read empty newline
-            let _ = self.read_line()?;
-            let result = self.read_line()?;
-            if result != "OK" {
-                return Err(ProtoError::NotOk)?;
+            let mut chunk = match self.execute(request::binary_chunk(cmd, path, transfer.received()))? {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            };
+
+            // Only one chunk is kept in memory, and it's read completely
+            // before writing it out, so a failing writer doesn't break the connection
+            out.write_all(&chunk.data)?;
+            let info = transfer.feed(&mut chunk);
+            progress(transfer.received(), chunk.size);
+            if info.is_some() {
+                out.flush()?;
+                return Ok(info);
             }
 
-            if size.parse::<usize>()? == buf.len() {
-                break;
+            if let Some(limit) = transfer.raise_limit(chunk.size, self.binary_limit, self.max_binary_limit) {
+                match self.binarylimit(limit) {
+                    // Server doesn't support `binarylimit`, go on with the current limit
+                    Ok(()) | Err(Error::Server(_)) | Err(Error::Unsupported { .. }) => (),
+                    Err(e) => return Err(e),
+                }
             }
         }
-        Ok(buf)
     }
 
     /// Case-insensitively search for songs matching Query conditions.
-    pub fn search<W>(&mut self, query: &Query, window: W) -> Result<Vec<Song>>
+    ///
+    /// See [`find`](#method.find) for `sort` and `window` arguments.
+    pub fn search<'a, O, W>(&mut self, query: &Query, sort: O, window: W) -> Result<Vec<Song>>
     where
+        O: Into<Sort<'a>>,
         W: Into<Window>,
     {
-        self.find_generic("search", query, window.into())
+        self.find_generic("search", query, sort.into(), window.into())
     }
 
-    fn find_generic(&mut self, cmd: &str, query: &Query, window: Window) -> Result<Vec<Song>> {
-        self.run_command(cmd, (query, window)).and_then(|_| self.read_structs("file"))
+    fn find_generic(&mut self, cmd: &str, query: &Query, sort: Sort, window: Window) -> Result<Vec<Song>> {
+        self.execute(request::find(cmd, query, sort, window))
     }
 
     /// Lists unique tags values of the specified type for songs matching the given query.
-    // TODO: list type [filtertype] [filterwhat] [...] [group] [grouptype] [...]
-    // It isn't clear if or how `group` works
-    pub fn list(&mut self, term: &Term, query: &Query) -> Result<Vec<String>> {
-        self.run_command("list", (term, query))
-            .and_then(|_| self.read_pairs().map(|p| p.map(|p| p.1)).collect())
+    ///
+    /// Values are always sorted by the server, and limited to `window` (use `None` to get all values).
+    /// A window requires protocol version 0.24.
+    pub fn list<W>(&mut self, term: &Term, query: &Query, window: W) -> Result<Vec<String>>
+    where
+        W: Into<Window>,
+    {
+        self.execute(request::list(term, query, window.into()))
+    }
+
+    /// Lists unique tags values of the specified type for songs matching the given query,
+    /// grouped by one or more other tags.
+    ///
+    /// The first group tag is the outermost one, e.g. listing `album` grouped by
+    /// `albumartist` and `date` gives album artists, each with its dates, each with its albums.
+    ///
+    /// See [`list`](#method.list) for `window` argument.
+    pub fn list_grouped<W>(&mut self, term: &Term, query: &Query, groups: &[Term], window: W) -> Result<Vec<ListGroup>>
+    where
+        W: Into<Window>,
+    {
+        self.execute(request::list_grouped(term, query, groups, window.into()))
     }
 
     /// Find all songs in the db that match query and adds them to current playlist.
-    pub fn findadd(&mut self, query: &Query) -> Result<()> {
-        self.run_command("findadd", query).and_then(|_| self.expect_ok())
+    ///
+    /// See [`find`](#method.find) for `sort` and `window` arguments.
+    /// If `pos` is given, songs are inserted at this queue position instead of appended.
+    pub fn findadd<'a, O, W>(&mut self, query: &Query, sort: O, window: W, pos: Option<Position>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::findadd("findadd", query, sort.into(), window.into(), pos))
+    }
+
+    /// Case-insensitively search for songs matching Query conditions and adds them to current playlist.
+    ///
+    /// See [`findadd`](#method.findadd) for the rest of arguments.
+    pub fn searchadd<'a, O, W>(&mut self, query: &Query, sort: O, window: W, pos: Option<Position>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::findadd("searchadd", query, sort.into(), window.into(), pos))
+    }
+
+    /// Case-insensitively search for songs matching Query conditions and adds them to a playlist.
+    ///
+    /// If playlist with given name doesn't exist, create new one.
+    /// See [`findadd`](#method.findadd) for the rest of arguments, `pos` being a position in the playlist.
+    pub fn searchaddpl<'a, O, W>(&mut self, name: &str, query: &Query, sort: O, window: W, pos: Option<u32>) -> Result<()>
+    where
+        O: Into<Sort<'a>>,
+        W: Into<Window>,
+    {
+        self.execute(request::searchaddpl(name, query, sort.into(), window.into(), pos))
     }
 
     /// Lists the contents of a directory.
     pub fn lsinfo(&mut self, path: &str) -> Result<Vec<LsInfoResponse>> {
-        self.run_command("lsinfo", path)?;
-        Ok(self.read_structs(&["directory", "file", "playlist"][..])?)
+        self.execute(request::lsinfo("lsinfo", path))
+    }
+
+    /// Lazily lists names of all songs, directories and playlists in a directory, recursively.
+    ///
+    /// Songs only have `file` field set.
+    pub fn listall<'a>(&'a mut self, path: &str) -> Result<LsInfoIter<'a, S>> {
+        self.run_command("listall", path)?;
+        Ok(LsInfoIter::new(self.read_pairs()))
+    }
+
+    /// Lazily lists all songs, directories and playlists with metadata in a directory, recursively.
+    pub fn listallinfo<'a>(&'a mut self, path: &str) -> Result<LsInfoIter<'a, S>> {
+        self.run_command("listallinfo", path)?;
+        Ok(LsInfoIter::new(self.read_pairs()))
     }
 
     /// Returns raw metadata for file
@@ -488,92 +852,130 @@ impl<S: Read + Write> Client<S> {
     // Output methods {{{
     /// List all outputs
     pub fn outputs(&mut self) -> Result<Vec<Output>> {
-        self.run_command("outputs", ()).and_then(|_| self.read_structs("outputid"))
+        self.execute(request::outputs())
     }
 
     /// Set given output enabled state
     pub fn output(&mut self, id: u32, state: bool) -> Result<()> {
-        if state {
-            self.out_enable(id)
-        } else {
-            self.out_disable(id)
-        }
+        self.execute(request::output(id, state))
     }
 
     /// Disable given output
     pub fn out_disable(&mut self, id: u32) -> Result<()> {
-        self.run_command("disableoutput", id).and_then(|_| self.expect_ok())
+        self.execute(request::output(id, false))
     }
 
     /// Enable given output
     pub fn out_enable(&mut self, id: u32) -> Result<()> {
-        self.run_command("enableoutput", id).and_then(|_| self.expect_ok())
+        self.execute(request::output(id, true))
     }
 
     /// Toggle given output
     pub fn out_toggle(&mut self, id: u32) -> Result<()> {
-        self.run_command("toggleoutput", id).and_then(|_| self.expect_ok())
+        self.execute(request::out_toggle(id))
     }
     // }}}
 
     // Reflection methods {{{
     /// Get current music directory
     pub fn music_directory(&mut self) -> Result<String> {
-        self.run_command("config", ()).and_then(|_| self.read_field("music_directory"))
+        self.execute(request::music_directory())
     }
 
     /// List all available commands
     pub fn commands(&mut self) -> Result<Vec<String>> {
-        self.run_command("commands", ()).and_then(|_| self.read_list("command"))
+        self.execute(request::commands("commands"))
     }
 
     /// List all forbidden commands
     pub fn notcommands(&mut self) -> Result<Vec<String>> {
-        self.run_command("notcommands", ()).and_then(|_| self.read_list("command"))
+        self.execute(request::commands("notcommands"))
+    }
+
+    /// Check if a command (like `readpicture` or `sticker get`) can be used with this connection
+    ///
+    /// The command must be supported by the server protocol version, and be listed in
+    /// `commands()` but not in `notcommands()`. These lists are fetched once per connection
+    /// (and again after `login()`).
+    pub fn supports(&mut self, command: &str) -> Result<bool> {
+        if check_version(command, self.version).is_err() {
+            return Ok(false);
+        }
+        let commands = match self.commands {
+            Some(ref commands) => commands,
+            None => {
+                let commands = request::allowed_commands(self.commands()?, self.notcommands()?);
+                self.commands.get_or_insert(commands)
+            }
+        };
+        Ok(request::is_allowed(commands, command))
     }
 
     /// List all available URL handlers
     pub fn urlhandlers(&mut self) -> Result<Vec<String>> {
-        self.run_command("urlhandlers", ()).and_then(|_| self.read_list("handler"))
+        self.execute(request::urlhandlers())
     }
 
     /// List all supported tag types
     pub fn tagtypes(&mut self) -> Result<Vec<String>> {
-        self.run_command("tagtypes", ()).and_then(|_| self.read_list("tagtype"))
+        self.execute(request::tagtypes())
+    }
+
+    /// Disable all tag types in responses for the current connection
+    pub fn tagtypes_clear(&mut self) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes clear", &[]))
+    }
+
+    /// Enable all tag types in responses for the current connection
+    pub fn tagtypes_all(&mut self) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes all", &[]))
+    }
+
+    /// Enable given tag types in responses for the current connection
+    pub fn tagtypes_enable(&mut self, tags: &[&str]) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes enable", tags))
+    }
+
+    /// Disable given tag types in responses for the current connection
+    pub fn tagtypes_disable(&mut self, tags: &[&str]) -> Result<()> {
+        self.execute(request::tagtypes_set("tagtypes disable", tags))
+    }
+
+    /// Switch the current connection to a given partition
+    pub fn partition(&mut self, name: &str) -> Result<()> {
+        self.execute(request::partition(name))
     }
 
     /// List all available decoder plugins
     pub fn decoders(&mut self) -> Result<Vec<Plugin>> {
-        self.run_command("decoders", ()).and_then(|_| self.read_struct())
+        self.execute(request::decoders())
     }
     // }}}
 
     // Messaging {{{
     /// List all channels available for current connection
     pub fn channels(&mut self) -> Result<Vec<Channel>> {
-        self.run_command("channels", ())
-            .and_then(|_| self.read_list("channel"))
-            .map(|v| v.into_iter().map(|b| unsafe { Channel::new_unchecked(b) }).collect())
+        self.execute(request::channels())
     }
 
     /// Read queued messages from subscribed channels
     pub fn readmessages(&mut self) -> Result<Vec<Message>> {
-        self.run_command("readmessages", ()).and_then(|_| self.read_structs("channel"))
+        self.execute(request::readmessages())
     }
 
     /// Send a message to a channel
     pub fn sendmessage(&mut self, channel: Channel, message: &str) -> Result<()> {
-        self.run_command("sendmessage", (channel, message)).and_then(|_| self.expect_ok())
+        self.execute(request::sendmessage(channel, message))
     }
 
     /// Subscribe to a channel
     pub fn subscribe(&mut self, channel: Channel) -> Result<()> {
-        self.run_command("subscribe", channel).and_then(|_| self.expect_ok())
+        self.execute(request::subscribe("subscribe", channel))
     }
 
     /// Unsubscribe to a channel
     pub fn unsubscribe(&mut self, channel: Channel) -> Result<()> {
-        self.run_command("unsubscribe", channel).and_then(|_| self.expect_ok())
+        self.execute(request::subscribe("unsubscribe", channel))
     }
     // }}}
 
@@ -582,152 +984,416 @@ impl<S: Read + Write> Client<S> {
     ///
     /// These mounts exist inside MPD process only, thus they can work without root permissions.
     pub fn mounts(&mut self) -> Result<Vec<Mount>> {
-        self.run_command("listmounts", ()).and_then(|_| self.read_structs("mount"))
+        self.execute(request::mounts())
     }
 
     /// List all network neighbors, which can be potentially mounted
     pub fn neighbors(&mut self) -> Result<Vec<Neighbor>> {
-        self.run_command("listneighbors", ()).and_then(|_| self.read_structs("neighbor"))
+        self.execute(request::neighbors())
     }
 
     /// Mount given neighbor to a mount point
     ///
     /// The mount exists inside MPD process only, thus it can work without root permissions.
     pub fn mount(&mut self, path: &str, uri: &str) -> Result<()> {
-        self.run_command("mount", (path, uri)).and_then(|_| self.expect_ok())
+        self.execute(request::mount(path, uri))
     }
 
     /// Unmount given active (virtual) mount
     ///
     /// The mount exists inside MPD process only, thus it can work without root permissions.
     pub fn unmount(&mut self, path: &str) -> Result<()> {
-        self.run_command("unmount", path).and_then(|_| self.expect_ok())
+        self.execute(request::unmount(path))
     }
     // }}}
 
     // Sticker methods {{{
     /// Show sticker value for a given object, identified by type and uri
     pub fn sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<String> {
-        self.run_command("sticker get", (typ, uri, name))
-            // TODO: This should parse to a `Sticker` type.
-            .and_then(|_| self.read_field::<Sticker>("sticker"))
-            .map(|s| s.value)
+        self.execute(request::sticker(typ, uri, name))
     }
 
     /// Set sticker value for a given object, identified by type and uri
     pub fn set_sticker(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<()> {
-        self.run_command("sticker set", (typ, uri, name, value))
-            .and_then(|_| self.expect_ok())
+        self.execute(request::set_sticker(typ, uri, name, value))
     }
 
     /// Delete sticker from a given object, identified by type and uri
     pub fn delete_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<()> {
-        self.run_command("sticker delete", (typ, uri, name)).and_then(|_| self.expect_ok())
+        self.execute(request::delete_sticker(typ, uri, name))
     }
 
     /// Remove all stickers from a given object, identified by type and uri
     pub fn clear_stickers(&mut self, typ: &str, uri: &str) -> Result<()> {
-        self.run_command("sticker delete", (typ, uri)).and_then(|_| self.expect_ok())
+        self.execute(request::clear_stickers(typ, uri))
     }
 
     /// List all stickers from a given object, identified by type and uri
     pub fn stickers(&mut self, typ: &str, uri: &str) -> Result<Vec<String>> {
-        self.run_command("sticker list", (typ, uri))
-            .and_then(|_| self.read_list("sticker"))
-            .map(|v| {
-                v.into_iter()
-                    .map(|b| b.splitn(2, '=').nth(1).map(|s| s.to_owned()).unwrap())
-                    .collect()
-            })
+        self.execute(request::stickers(typ, uri))
     }
 
     /// List all stickers from a given object in a map, identified by type and uri
     pub fn stickers_map(&mut self, typ: &str, uri: &str) -> Result<HashMap<String, String>> {
-        self.run_command("sticker list", (typ, uri))
-            .and_then(|_| self.read_list("sticker"))
-            .map(|v| {
-                v.into_iter()
-                    .map(|b| {
-                        let mut iter = b.splitn(2, '=');
-
-                        (iter.next().unwrap().to_owned(), iter.next().unwrap().to_owned())
-                    })
-                    .collect()
-            })
+        self.execute(request::stickers_map(typ, uri))
     }
 
     /// List all (file, sticker) pairs for sticker name and objects of given type
     /// from given directory (identified by uri)
     pub fn find_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<Vec<(String, String)>> {
-        self.run_command("sticker find", (typ, uri, name)).and_then(|_| {
-            self.read_pairs()
-                .split("file")
-                .map(|rmap| {
-                    rmap.map(|map| {
-                        (
-                            map.iter()
-                                .filter_map(|(k, v)| if k == "file" { Some(v.to_owned()) } else { None })
-                                .next()
-                                .unwrap(),
-                            map.iter()
-                                .filter_map(|(k, v)| if k == "sticker" { Some(v.to_owned()) } else { None })
-                                .next()
-                                .and_then(|s| s.splitn(2, '=').nth(1).map(|s| s.to_owned()))
-                                .unwrap(),
-                        )
-                    })
-                })
-                .collect()
-        })
+        self.execute(request::find_sticker(typ, uri, name))
     }
 
     /// List all files of a given type under given directory (identified by uri)
     /// with a tag set to given value
     pub fn find_sticker_eq(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<Vec<String>> {
-        self.run_command("sticker find", (typ, uri, name, value))
-            .and_then(|_| self.read_list("file"))
+        self.execute(request::find_sticker_eq(typ, uri, name, value))
     }
     // }}}
 }
 
 // Helper methods {{{
-impl<S: Read + Write> Proto for Client<S> {
-    type Stream = S;
-
-    fn read_bytes(&mut self, bytes: usize) -> Result<Vec<u8>> {
-        let mut buf = Vec::with_capacity(bytes);
-        let mut chunk = (&mut self.socket).take(bytes as u64);
-        chunk.read_to_end(&mut buf)?;
-        Ok(buf)
+impl<S: Read + Write> Client<S> {
+    /// Send a request and parse its response
+    fn execute<T>(&mut self, request: Result<Request<T>>) -> Result<T> {
+        let request = request?;
+        request.check(self.version)?;
+        self.write_raw(request.encoded())?;
+        request.parse(self)
     }
+}
 
-    fn read_line(&mut self) -> Result<String> {
-        let mut buf = Vec::new();
-        self.socket.read_until(b'\n', &mut buf)?;
-        if buf.ends_with(&[b'\n']) {
-            buf.pop();
+impl<S: Read + Write> Source for Client<S> {
+    fn read_event(&mut self) -> Result<Event> {
+        let mut buf = [0; 8192];
+        loop {
+            if let Some(event) = self.decoder.decode()? {
+                return Ok(event);
+            }
+            match self.socket.read(&mut buf) {
+                Ok(0) => return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))),
+                Ok(n) => self.decoder.feed(&buf[..n]),
+                Err(e) if self.read_timeout => return Err(Error::timed_out(e)),
+                Err(e) => return Err(Error::Io(e)),
+            }
         }
-        let str = String::from_utf8(buf)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))?;
-        Ok(str)
     }
 
-    fn read_pairs(&mut self) -> Pairs<Lines<&mut BufStream<S>>> {
-        Pairs((&mut self.socket).lines())
+    fn limits(&self) -> Limits {
+        self.decoder.limits()
     }
+}
 
-    fn run_command<I>(&mut self, command: &str, arguments: I) -> Result<()>
-    where
-        I: ToArguments,
-    {
-        self.socket
-            .write_all(command.as_bytes())
-            .and_then(|_| arguments.to_arguments(&mut |arg| write!(self.socket, " {}", Quoted(arg))))
-            .and_then(|_| self.socket.write(&[0x0a]))
-            .and_then(|_| self.socket.flush())
-            .map_err(From::from)
+impl<S: Read + Write> Proto for Client<S> {
+    type Stream = S;
+
+    fn check_command(&self, command: &str) -> Result<()> {
+        check_version(command, self.version)
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        self.last_write = Instant::now();
+        self.socket.write_all(data).and_then(|_| self.socket.flush()).map_err(From::from)
     }
 }
 // }}}
 
 // }}}
+
+#[cfg(test)]
+mod tests {
+    use super::{Client, Limits};
+    use crate::codec::encode_command;
+    use crate::connect::ConnectOptions;
+    use crate::error::{Error, ParseError, ProtoError, ServerError};
+    use crate::idle::Idle;
+    use crate::message::Channel;
+    use crate::search::{Expression, FilterQuery, Query, SortKey, Term};
+    use crate::test_util::Mock;
+    use crate::version::Version;
+    use std::io::{self, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    fn client(response: &[u8]) -> Client<Mock> {
+        client_version("0.23.0", response)
+    }
+
+    fn client_version(version: &str, response: &[u8]) -> Client<Mock> {
+        client_options(version, response, &ConnectOptions::new()).unwrap()
+    }
+
+    fn client_options(version: &str, response: &[u8], options: &ConnectOptions) -> Result<Client<Mock>, Error> {
+        let mut data = format!("OK MPD {}\n", version).into_bytes();
+        data.extend_from_slice(response);
+        Client::with_options(Mock::new(&data), options)
+    }
+
+    fn written(client: &Client<Mock>) -> String {
+        client.socket.written()
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let response = b"file: caf\xe9.flac\nTitle: Caf\xe9\nPos: 0\nId: 1\nOK\n";
+
+        assert!(client(response).currentsong().is_err());
+
+        let mut mpd = client(b"directory: caf\xe9\nfile: caf\xe9/a.flac\nfile: b.flac\nOK\nOK\n");
+        let entries = mpd.listallinfo("").unwrap().collect::<Vec<_>>();
+        assert!(matches!(entries[0], Err(Error::Parse(ParseError::BadUtf8(_)))));
+        assert_eq!(entries.len(), 3);
+        mpd.ping().unwrap();
+
+        let mut mpd = client(b"directory: caf\xe9\nfile: b.flac\nOK\nOK\n");
+        assert!(matches!(mpd.lsinfo(""), Err(Error::Parse(ParseError::BadUtf8(_)))));
+        mpd.ping().unwrap();
+
+        let mut mpd = client(response);
+        mpd.set_lossy_utf8(true);
+        let song = mpd.currentsong().unwrap().unwrap();
+        assert_eq!(song.file, "caf\u{fffd}.flac");
+        let lines = mpd.take_lossy_lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].raw, b"file: caf\xe9.flac");
+        assert_eq!(lines[1].line, "Title: Caf\u{fffd}");
+        assert!(mpd.take_lossy_lines().is_empty());
+    }
+
+    #[test]
+    fn list_grouped() {
+        let mut mpd = client_version("0.24.0", b"AlbumArtist: A\nAlbum: X\nAlbum: Y\nOK\n");
+        let query = Query::from(FilterQuery::new());
+        let groups = mpd
+            .list_grouped(&Term::Tag("album"), &query, &[Term::Tag("albumartist")], (0, 2))
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].values, vec!["X", "Y"]);
+        assert_eq!(written(&mpd), "list \"album\" \"group\" \"albumartist\" \"window\" \"0:2\"\n");
+    }
+
+    #[test]
+    fn listall_drop() {
+        let mut mpd = client(b"file: a.flac\nbad line\nfile: b.flac\nTitle: a very long title\nfile: c.flac\nOK\nOK\n");
+        let mut limits = mpd.limits();
+        limits.line_length = 20;
+        mpd.set_limits(limits);
+        {
+            let mut songs = mpd.listall("").unwrap();
+            assert!(matches!(songs.next(), Some(Err(Error::Parse(_)))));
+        }
+        mpd.ping().unwrap();
+    }
+
+    #[test]
+    fn command_list_errors() {
+        let mut mpd = client(b"volume: 50\nlist_OK\nbad line\nlist_OK\nOK\nOK\n");
+        let mut list = mpd.command_list();
+        let status = list.status();
+        let stats = list.stats();
+        let mut replies = list.run().unwrap();
+        assert_eq!(replies.take(status).unwrap().volume, 50);
+        assert!(matches!(replies.take(stats), Err(Error::Parse(_))));
+        mpd.ping().unwrap();
+
+        // Commands are checked against server version
+        let mut mpd = client_version("0.16.0", b"");
+        let mut list = mpd.command_list();
+        list.prioid(1, 10);
+        assert!(matches!(list.run(), Err(Error::Unsupported { .. })));
+        assert_eq!(written(&mpd), "");
+    }
+
+    #[test]
+    fn pipeline_errors() {
+        let mut mpd = client(b"bad line\nvolume: 50\nOK\nACK [50@0] {add} no such file\nOK\nOK\n");
+        let mut pipeline = mpd.pipeline();
+        let status = pipeline.status();
+        pipeline.add("foo.flac");
+        pipeline.deleteid(1);
+        let mut pending = pipeline.send().unwrap();
+        assert!(matches!(pending.take(status), Err(Error::Parse(_))));
+        assert!(matches!(pending.finish(), Err(Error::Server(_))));
+        mpd.ping().unwrap();
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let mut mpd = client(b"");
+        assert!(matches!(mpd.add("foo\nclear"), Err(Error::BadArgument(_))));
+        assert!(matches!(mpd.add("foo\0"), Err(Error::BadArgument(_))));
+        assert!(matches!(mpd.raw_command("status\nclear", &[]), Err(Error::BadArgument(_))));
+        assert!(matches!(mpd.raw_command("", &[]), Err(Error::BadArgument(_))));
+        assert!(matches!(mpd.raw_command("sticker  list", &[]), Err(Error::BadArgument(_))));
+        let channel = unsafe { Channel::new_unchecked("foo bar".into()) };
+        assert!(matches!(mpd.subscribe(channel), Err(Error::BadArgument(_))));
+        assert!(Channel::new("").is_none());
+        let query = Query::from(Expression::And(vec![]));
+        assert!(matches!(mpd.find(&query, None, None), Err(Error::BadArgument(_))));
+
+        // Multi-word commands are fine, but not with stray spaces
+        let mut buf = Vec::new();
+        encode_command(&mut buf, "sticker get", ("song", "a.flac", "rating")).unwrap();
+        assert_eq!(buf, b"sticker get \"song\" \"a.flac\" \"rating\"\n");
+        assert!(matches!(encode_command(&mut buf, "sticker  get", ()), Err(Error::BadArgument(_))));
+        assert!(matches!(encode_command(&mut buf, "tagtypes clear ", ()), Err(Error::BadArgument(_))));
+    }
+
+    #[test]
+    fn malformed_responses() {
+        let mut mpd = client(b"plugin: flac\nsuffix: flac\nunknown: x\nOK\n");
+        assert_eq!(mpd.decoders().unwrap()[0].suffixes, vec!["flac"]);
+
+        let mut mpd = client(b"sticker: rating\nOK\n");
+        assert!(matches!(mpd.stickers("song", "foo.flac"), Err(Error::Proto(ProtoError::BadSticker))));
+
+        let mut mpd = client(b"sticker: rating=5\nOK\n");
+        assert!(matches!(mpd.find_sticker("song", "", "rating"), Err(Error::Proto(ProtoError::NoField("file")))));
+
+        assert!("ACK ]50@0[ }status{ x".parse::<ServerError>().is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let mut mpd = client(b"file: a\nfile: b\nOK\nfile: a\nOK\n");
+        mpd.set_limits(Limits {
+            records: 1,
+            ..Limits::default()
+        });
+        assert!(matches!(mpd.queue(), Err(Error::Proto(ProtoError::TooLarge("records")))));
+        assert_eq!(mpd.queue().unwrap().len(), 1);
+
+        let mut mpd = client(b"file: a-very-long-line\nfile: b\nOK\nfile: b\nOK\nfile: a-very-long-line\nOK\nOK\n");
+        mpd.set_limits(Limits {
+            line_length: 10,
+            ..Limits::default()
+        });
+        assert!(matches!(mpd.queue(), Err(Error::Proto(ProtoError::TooLarge("line")))));
+        assert_eq!(mpd.queue().unwrap().len(), 1);
+        assert!(matches!(mpd.currentsong(), Err(Error::Proto(ProtoError::TooLarge("line")))));
+        mpd.ping().unwrap();
+
+        let mut mpd = client(b"size: 1000\nbinary: 3\nabc\nOK\nOK\n");
+        mpd.set_limits(Limits {
+            binary_size: 100,
+            ..Limits::default()
+        });
+        assert!(matches!(mpd.albumart("foo.flac"), Err(Error::Proto(ProtoError::TooLarge("binary")))));
+        mpd.ping().unwrap();
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut mpd = client_version("0.20.0", b"command: status\ncommand: sticker\ncommand: albumart\nOK\ncommand: sticker\nOK\n");
+        match mpd.albumart("foo.flac") {
+            Err(Error::Unsupported { command, required, actual }) => {
+                assert_eq!(command, "albumart");
+                assert_eq!(required, Version(0, 21, 0));
+                assert_eq!(actual, Version(0, 20, 0));
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(matches!(mpd.findadd(&Query::from(FilterQuery::default()), None, None, Some(0.into())), Err(Error::Unsupported { .. })));
+
+        assert!(mpd.supports("status").unwrap());
+        assert!(!mpd.supports("sticker get").unwrap());
+        assert!(!mpd.supports("albumart").unwrap());
+        assert!(!mpd.supports("listpartitions").unwrap());
+        assert!(!mpd.supports("playlistinfo").unwrap());
+    }
+
+    #[test]
+    fn cover_art_fallback() {
+        // `readpicture` is not supported before 0.22, and album art is used instead
+        let mut mpd = client_version("0.21.0", b"size: 3\nbinary: 3\nabc\nOK\n");
+        let picture = mpd.cover_art("a.flac").unwrap().unwrap();
+        assert_eq!(picture.data, b"abc");
+        assert_eq!(picture.mime, None);
+        assert_eq!(written(&mpd), "albumart \"a.flac\" \"0\"\n");
+    }
+
+    #[test]
+    fn binary_to_writer() {
+        let mut mpd = client(b"size: 5\ntype: image/png\nbinary: 3\nabc\nOK\nsize: 5\nbinary: 2\nde\nOK\n");
+        let mut progress = Vec::new();
+        let mut out = io::BufWriter::new(Vec::new());
+        let info = mpd.readpicture_to("a.flac", 0, &mut out, |received, size| progress.push((received, size)));
+        assert_eq!(info.unwrap().unwrap().mime.as_deref(), Some("image/png"));
+        assert_eq!(progress, vec![(3, 5), (5, 5)]);
+        // A buffered writer is flushed after the last chunk
+        assert_eq!(out.get_ref(), b"abcde");
+    }
+
+    #[test]
+    fn unsupported_arguments() {
+        fn required<T: std::fmt::Debug>(result: Result<T, Error>) -> (String, Version) {
+            match result {
+                Err(Error::Unsupported { command, required, .. }) => (command, required),
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+
+        let all = Query::from(Expression::eq(Term::Any, "a"));
+        let mut mpd = client_version("0.19.0", b"");
+        assert_eq!(required(mpd.find(&all, None, (0, 2))), ("find window".into(), Version(0, 20, 0)));
+
+        let mut mpd = client_version("0.20.0", b"file: a.flac\nOK\n");
+        assert_eq!(mpd.search(&all, None, (0, 2)).unwrap().len(), 1);
+        assert_eq!(required(mpd.search(&all, SortKey::Added, None)), ("search sort".into(), Version(0, 21, 0)));
+        assert_eq!(required(mpd.findadd(&all, None, (0, 2), None)), ("findadd window".into(), Version(0, 21, 0)));
+        assert_eq!(required(mpd.searchadd(&all, SortKey::Tag("date"), None, None)), ("searchadd sort".into(), Version(0, 21, 0)));
+        assert_eq!(required(mpd.count(&all, Some(Term::Tag("album")))), ("count group".into(), Version(0, 21, 0)));
+        assert_eq!(
+            required(mpd.list_grouped(&Term::Tag("album"), &all, &[Term::Tag("albumartist")], None)),
+            ("list group".into(), Version(0, 21, 0))
+        );
+        assert_eq!(written(&mpd), "search \"(any == \\\"a\\\")\" \"window\" \"0:2\"\n");
+
+        let mut mpd = client_version("0.23.0", b"");
+        assert_eq!(required(mpd.list(&Term::Tag("album"), &all, (0, 2))), ("list window".into(), Version(0, 24, 0)));
+    }
+
+    #[test]
+    fn connect_options() {
+        let mut options = ConnectOptions::new();
+        options
+            .password("secret")
+            .binary_limit(65536)
+            .tagtypes(&["Artist", "Title"])
+            .partition("other");
+        let mpd = client_options("0.23.0", b"OK\nOK\nOK\nOK\nOK\n", &options).unwrap();
+        assert_eq!(
+            written(&mpd),
+            "password \"secret\"\nbinarylimit \"65536\"\ntagtypes clear\ntagtypes enable \"Artist\" \"Title\"\npartition \"other\"\n"
+        );
+        assert_eq!(mpd.binary_limit(), 65536);
+
+        let error = client_options("0.23.0", b"ACK [3@0] {password} incorrect password\n", &options).unwrap_err();
+        assert!(matches!(error, Error::Server(_)));
+
+        // Only reads with a timeout set time out
+        let error: Error = io::Error::new(io::ErrorKind::WouldBlock, "would block").into();
+        assert!(matches!(error, Error::Io(_)));
+        let error = Error::timed_out(io::Error::new(io::ErrorKind::WouldBlock, "timed out"));
+        assert!(matches!(error, Error::Timeout));
+
+        let (socket, mut server) = UnixStream::pair().unwrap();
+        server.write_all(b"OK MPD 0.23.0\n").unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        let mut mpd = Client::with_options(socket, ConnectOptions::new().read_timeout(Duration::from_millis(10))).unwrap();
+        assert!(matches!(mpd.ping(), Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn keepalive() {
+        let mut mpd = client(b"OK\n");
+        assert!(!mpd.keepalive(Duration::from_secs(60)).unwrap());
+        assert!(mpd.keepalive(Duration::from_secs(0)).unwrap());
+        assert_eq!(written(&mpd), "ping\n");
+
+        // Never ping in idle mode
+        std::mem::forget(mpd.idle(&[]).unwrap());
+        assert!(!mpd.keepalive(Duration::from_secs(0)).unwrap());
+        assert_eq!(written(&mpd), "ping\nidle\n");
+    }
+}
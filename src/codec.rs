@@ -0,0 +1,343 @@
+//! The module defines a sans-IO implementation of MPD protocol
+//!
+//! [`Decoder`](struct.Decoder.html) is a state machine, which takes bytes received from
+//! the server and turns them into [`Event`](enum.Event.html)s: server greeting, data pairs,
+//! binary chunks and end of responses (`OK`, `list_OK` or `ACK`).
+//! Commands are encoded with [`encode_command`](fn.encode_command.html).
+//!
+//! It doesn't do any IO itself, so it can be driven by any event loop (like mio or glib),
+//! feeding bytes as they arrive from a socket. Both [`Client`](../client/struct.Client.html)
+//! and `AsyncClient` are thin drivers on top of it: commands and parsers of their responses
+//! are defined once for both of them, and only reading and writing bytes differs.
+//!
+//! ```rust
+//! use mpd::codec::{encode_command, Decoder, Event};
+//! use mpd::Version;
+//!
+//! let mut request = Vec::new();
+//! encode_command(&mut request, "albumart", ("foo.flac", 0u32)).unwrap();
+//! assert_eq!(request, b"albumart \"foo.flac\" \"0\"\n");
+//!
+//! let mut decoder = Decoder::new();
+//! decoder.feed(b"OK MPD 0.23.0\nsize: 3\nbinary: 3\nab");
+//! assert_eq!(decoder.decode().unwrap(), Some(Event::Greeting(Version(0, 23, 0))));
+//! assert_eq!(decoder.decode().unwrap(), Some(Event::Pair("size".into(), "3".into())));
+//! assert_eq!(decoder.decode().unwrap(), Some(Event::Pair("binary".into(), "3".into())));
+//! // More bytes are needed
+//! assert_eq!(decoder.decode().unwrap(), None);
+//!
+//! decoder.feed(b"c\nOK\n");
+//! assert_eq!(decoder.decode().unwrap(), Some(Event::Binary(b"abc".to_vec())));
+//! assert_eq!(decoder.decode().unwrap(), Some(Event::Ok));
+//! ```
+
+use crate::client::{Limits, LossyLine};
+use crate::error::{Error, ParseError, ProtoError, Result, ServerError};
+use crate::proto::{Quoted, ToArguments};
+use crate::reply::Reply;
+use crate::version::Version;
+
+use std::io::Write;
+use std::mem;
+
+/// Protocol event decoded from server data
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// server greeting (`OK MPD <version>`), the first line of every connection
+    Greeting(Version),
+    /// a data pair (in `field: value` format)
+    Pair(String, String),
+    /// binary data, which follows a `binary: <size>` pair
+    Binary(Vec<u8>),
+    /// end of a command reply inside a command list (`list_OK`)
+    ListOk,
+    /// successful end of a response (`OK`)
+    Ok,
+    /// failed end of a response (`ACK`)
+    Ack(ServerError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Greeting,
+    Lines,
+    Binary(usize),
+    // rest of a rejected line or binary chunk
+    SkipLine,
+    SkipBytes(usize),
+}
+
+/// Sans-IO decoder of server responses
+///
+/// Feed it with bytes as they arrive, and call [`decode`](#method.decode) until it
+/// returns `Ok(None)`, which means more bytes are needed. An error is returned for
+/// a broken line, which is then skipped, so decoding can go on.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    pos: usize,
+    state: State,
+    limits: Limits,
+    lossy_utf8: bool,
+    lossy_lines: Vec<LossyLine>,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    /// Create decoder for a new connection, expecting server greeting first
+    pub fn new() -> Decoder {
+        Decoder {
+            buf: Vec::new(),
+            pos: 0,
+            state: State::Greeting,
+            limits: Limits::default(),
+            lossy_utf8: false,
+            lossy_lines: Vec::new(),
+        }
+    }
+
+    /// Append bytes received from the server
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Number of bytes fed, but not decoded yet
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Decode next event, returns `None` if more bytes are needed
+    pub fn decode(&mut self) -> Result<Option<Event>> {
+        loop {
+            let rest = &self.buf[self.pos..];
+            match self.state {
+                State::Binary(size) => {
+                    if rest.len() <= size {
+                        return Ok(None);
+                    }
+                    let data = rest[..size].to_vec();
+                    let newline = rest[size] == b'\n';
+                    self.pos += size + 1;
+                    self.state = State::Lines;
+                    if !newline {
+                        return Err(Error::Proto(ProtoError::BadBinary));
+                    }
+                    return Ok(Some(Event::Binary(data)));
+                }
+                State::SkipBytes(size) => {
+                    let skipped = size.min(rest.len());
+                    self.pos += skipped;
+                    if skipped < size {
+                        self.state = State::SkipBytes(size - skipped);
+                        return Ok(None);
+                    }
+                    self.state = State::Lines;
+                }
+                State::SkipLine => match rest.iter().position(|&b| b == b'\n') {
+                    Some(end) => {
+                        self.pos += end + 1;
+                        self.state = State::Lines;
+                    }
+                    None => {
+                        self.pos = self.buf.len();
+                        return Ok(None);
+                    }
+                },
+                State::Greeting | State::Lines => {
+                    let max = self.limits.line_length;
+                    let end = match rest.iter().position(|&b| b == b'\n') {
+                        Some(end) if end <= max => end,
+                        Some(end) => {
+                            self.pos += end + 1;
+                            return Err(Error::Proto(ProtoError::TooLarge("line")));
+                        }
+                        None if rest.len() > max => {
+                            self.pos = self.buf.len();
+                            self.state = State::SkipLine;
+                            return Err(Error::Proto(ProtoError::TooLarge("line")));
+                        }
+                        None => return Ok(None),
+                    };
+                    let raw = rest[..end].to_vec();
+                    self.pos += end + 1;
+                    let line = self.decode_line(raw)?;
+                    return self.parse_line(line).map(Some);
+                }
+            }
+        }
+    }
+
+    fn decode_line(&mut self, raw: Vec<u8>) -> Result<String> {
+        match String::from_utf8(raw) {
+            Ok(line) => Ok(line),
+            Err(e) if self.lossy_utf8 => {
+                let raw = e.into_bytes();
+                let line = String::from_utf8_lossy(&raw).into_owned();
+                self.lossy_lines.push(LossyLine { line: line.clone(), raw });
+                Ok(line)
+            }
+            Err(e) => Err(Error::Parse(ParseError::BadUtf8(e.utf8_error()))),
+        }
+    }
+
+    fn parse_line(&mut self, line: String) -> Result<Event> {
+        if self.state == State::Greeting {
+            self.state = State::Lines;
+            let version = line.strip_prefix("OK MPD ").ok_or(Error::Proto(ProtoError::BadBanner))?;
+            return Ok(Event::Greeting(version.trim().parse()?));
+        }
+
+        if line == "list_OK" {
+            return Ok(Event::ListOk);
+        }
+        match line.parse::<Reply>()? {
+            Reply::Ok => Ok(Event::Ok),
+            Reply::Ack(e) => Ok(Event::Ack(e)),
+            Reply::Pair(a, b) => {
+                if a == "binary" {
+                    let size = b.parse::<usize>()?;
+                    if size > self.limits.binary_size {
+                        // the size comes from the server, so it can be anything up to `usize::MAX`
+                        self.state = State::SkipBytes(size.saturating_add(1));
+                        return Err(Error::Proto(ProtoError::TooLarge("binary")));
+                    }
+                    self.state = State::Binary(size);
+                }
+                Ok(Event::Pair(a, b))
+            }
+        }
+    }
+
+    /// Set caps on server responses
+    ///
+    /// Only line length and binary size are checked here, records are counted by callers.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get current caps on server responses
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Set how to handle lines which are not valid UTF-8
+    ///
+    /// See [`Client::set_lossy_utf8`](../client/struct.Client.html#method.set_lossy_utf8).
+    pub fn set_lossy_utf8(&mut self, lossy: bool) {
+        self.lossy_utf8 = lossy;
+    }
+
+    /// Check if lines which are not valid UTF-8 are decoded lossily
+    pub fn lossy_utf8(&self) -> bool {
+        self.lossy_utf8
+    }
+
+    /// Take all lines decoded lossily since the last call
+    pub fn take_lossy_lines(&mut self) -> Vec<LossyLine> {
+        mem::take(&mut self.lossy_lines)
+    }
+}
+
+/// Append a command line with quoted arguments to a buffer
+///
+/// Command name must consist of words of ASCII letters, digits and underscores
+/// (like `sticker get`), and arguments
+/// must not contain newlines or NUL characters, otherwise they could inject other commands.
+/// On such an invalid command `Error::BadArgument` is returned, and the buffer is left intact.
+pub fn encode_command<I>(buf: &mut Vec<u8>, command: &str, arguments: I) -> Result<()>
+where
+    I: ToArguments,
+{
+    if !command
+        .split(' ')
+        .all(|w| !w.is_empty() && w.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'))
+    {
+        return Err(Error::BadArgument(command.to_owned()));
+    }
+
+    let start = buf.len();
+    buf.extend_from_slice(command.as_bytes());
+    let result = arguments.to_arguments(&mut |arg| {
+        if arg.contains(['\n', '\0']) {
+            return Err(Error::BadArgument(arg.to_owned()));
+        }
+        write!(buf, " {}", Quoted(arg)).map_err(Error::Io)
+    });
+    if let Err(e) = result {
+        buf.truncate(start);
+        return Err(e);
+    }
+    buf.push(b'\n');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Event};
+    use crate::client::Limits;
+    use crate::error::{Error, ProtoError};
+
+    fn decoder(data: &[u8]) -> Decoder {
+        let mut decoder = Decoder::new();
+        decoder.feed(b"OK MPD 0.23.0\n");
+        decoder.decode().unwrap();
+        decoder.feed(data);
+        decoder
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let data = b"file: a.flac\nlist_OK\nbinary: 2\n\n\n\nACK [50@1] {add} no such file\n";
+        let mut decoder = decoder(b"");
+        let mut events = Vec::new();
+        for b in data.iter() {
+            decoder.feed(&[*b]);
+            while let Some(event) = decoder.decode().unwrap() {
+                events.push(event);
+            }
+        }
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0], Event::Pair("file".into(), "a.flac".into()));
+        assert_eq!(events[1], Event::ListOk);
+        assert_eq!(events[3], Event::Binary(b"\n\n".to_vec()));
+        assert!(matches!(events[4], Event::Ack(ref e) if e.pos == 1));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn recovery() {
+        let mut decoder = decoder(b"bad line\nbinary: 3\nabc!OK\nfile: a-very-long-line\nbinary: 1000\n");
+        decoder.set_limits(Limits {
+            line_length: 15,
+            binary_size: 100,
+            ..Limits::default()
+        });
+        assert!(matches!(decoder.decode(), Err(Error::Parse(_))));
+        assert!(decoder.decode().unwrap().is_some());
+        assert!(matches!(decoder.decode(), Err(Error::Proto(ProtoError::BadBinary))));
+        assert_eq!(decoder.decode().unwrap(), Some(Event::Ok));
+        assert!(matches!(decoder.decode(), Err(Error::Proto(ProtoError::TooLarge("line")))));
+        assert!(matches!(decoder.decode(), Err(Error::Proto(ProtoError::TooLarge("binary")))));
+
+        // The rejected binary chunk is skipped
+        decoder.feed(&[0; 1001]);
+        decoder.feed(b"OK\n");
+        assert_eq!(decoder.decode().unwrap(), Some(Event::Ok));
+        assert!(matches!(Decoder::new().decode(), Ok(None)));
+
+        // A huge size doesn't overflow
+        decoder.set_limits(Limits::default());
+        decoder.feed(format!("binary: {}\nOK\n", usize::MAX).as_bytes());
+        assert!(matches!(decoder.decode(), Err(Error::Proto(ProtoError::TooLarge("binary")))));
+        assert_eq!(decoder.decode().unwrap(), None);
+    }
+}
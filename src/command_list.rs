@@ -0,0 +1,299 @@
+//! The module defines command lists, i.e. batches of commands sent to MPD in a single round trip
+//!
+//! Commands are queued with typed methods of [`CommandList`](struct.CommandList.html),
+//! each returning a [`Ticket`](struct.Ticket.html), which is later used to get
+//! the result of that particular command from [`Replies`](struct.Replies.html).
+//!
+//! ```rust,no_run
+//! # use mpd::Client;
+//! let mut conn = Client::connect("127.0.0.1:6600").unwrap();
+//! let mut list = conn.command_list();
+//! list.add("foo.flac");
+//! let id = list.push("bar.flac");
+//! let status = list.status();
+//! let mut replies = list.run().unwrap();
+//! println!("{:?} {:?}", replies.take(id), replies.take(status));
+//! ```
+//!
+//! MPD stops executing a command list at the first failing command. Its error is
+//! returned for its ticket (and is available via `Replies::error()`),
+//! and all commands after it fail with `ProtoError::Skipped`.
+//!
+//! The same typed commands (see [`Commands`](struct.Commands.html)) can be sent without
+//! a command list in a [`Pipeline`](../pipeline/struct.Pipeline.html), where each command
+//! succeeds or fails on its own.
+
+use crate::client::{Client, Limits};
+use crate::codec::Event;
+use crate::error::{Error, ProtoError, ServerError};
+use crate::proto::{Proto, Replay, Source};
+use crate::request::{self, Parser, Request};
+use crate::song::{Range, Song};
+use crate::stats::Stats;
+use crate::status::Status;
+use crate::version::Version;
+
+use std::io::{Read, Write};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+/// Reply to a single command: data pairs, or an error
+pub(crate) type CommandReply = Result<Vec<(String, String)>, Error>;
+
+/// Handle to a result of a queued command
+pub struct Ticket<T> {
+    index: usize,
+    parse: Parser<T>,
+}
+
+impl<T> Ticket<T> {
+    /// Position of the command in its command list or pipeline
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Parse the reply to the command
+    pub(crate) fn parse(self, pairs: Vec<(String, String)>, limits: Limits) -> Result<T, Error> {
+        (self.parse)(&mut Replay::from_pairs(pairs, limits))
+    }
+}
+
+/// Queue of commands with typed results
+///
+/// This is the part shared by [`CommandList`](struct.CommandList.html)
+/// and [`Pipeline`](../pipeline/struct.Pipeline.html), which both dereference to it.
+#[derive(Debug, Default)]
+pub struct Commands {
+    buf: Vec<u8>,
+    count: usize,
+    error: Option<Error>,
+    // server protocol version to check commands against
+    version: Option<Version>,
+}
+
+impl Commands {
+    pub(crate) fn new(version: Version) -> Commands {
+        Commands {
+            version: Some(version),
+            ..Commands::default()
+        }
+    }
+
+    fn enqueue<T>(&mut self, request: Result<Request<T>, Error>) -> Ticket<T> {
+        let version = self.version;
+        let request = request.and_then(|request| match version {
+            Some(version) => request.check(version).map(|_| request),
+            None => Ok(request),
+        });
+        let parse = match request {
+            Ok(request) => {
+                let (buf, parse) = request.into_parts();
+                self.buf.extend_from_slice(&buf);
+                parse
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+                // never called, as commands with an error are not sent
+                Box::new(|_: &mut dyn Source| Err(Error::Proto(ProtoError::Skipped)))
+            }
+        };
+        self.count += 1;
+        Ticket {
+            index: self.count - 1,
+            parse,
+        }
+    }
+
+    /// Get encoded commands, or the first error occurred while encoding them
+    pub(crate) fn encoded(&mut self) -> Result<&[u8], Error> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(&self.buf),
+        }
+    }
+
+    /// Number of queued commands
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Check if no commands were queued
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get MPD status (without replay gain mode)
+    pub fn status(&mut self) -> Ticket<Status> {
+        self.enqueue(request::status())
+    }
+
+    /// Get MPD playing statistics
+    pub fn stats(&mut self) -> Ticket<Stats> {
+        self.enqueue(request::stats())
+    }
+
+    /// Get current playing song
+    pub fn currentsong(&mut self) -> Ticket<Option<Song>> {
+        self.enqueue(request::currentsong())
+    }
+
+    /// List all songs in a play queue
+    pub fn queue(&mut self) -> Ticket<Vec<Song>> {
+        self.enqueue(request::queue())
+    }
+
+    /// List given song or range of songs in a play queue
+    pub fn playlistinfo<T: Into<Range>>(&mut self, pos: T) -> Ticket<Vec<Song>> {
+        self.enqueue(request::playlistinfo(pos.into()))
+    }
+
+    /// List all changes in a queue since given version
+    pub fn changes(&mut self, version: u32) -> Ticket<Vec<Song>> {
+        self.enqueue(request::changes(version))
+    }
+
+    /// Append all songs in path (directories add recursively) to the playlist
+    pub fn add(&mut self, path: &str) -> Ticket<()> {
+        self.enqueue(request::add(path))
+    }
+
+    /// Append a song into a queue
+    pub fn push(&mut self, path: &str) -> Ticket<u32> {
+        self.enqueue(request::push(path))
+    }
+
+    /// Insert a song into a given position in a queue
+    pub fn insert(&mut self, path: &str, pos: usize) -> Ticket<u32> {
+        self.enqueue(request::insert(path, pos))
+    }
+
+    /// Delete several songs (in a range) from a queue
+    pub fn delete<T: Into<Range>>(&mut self, pos: T) -> Ticket<()> {
+        self.enqueue(request::delete(pos.into()))
+    }
+
+    /// Delete a song from a queue
+    pub fn deleteid(&mut self, id: u32) -> Ticket<()> {
+        self.enqueue(request::deleteid(id))
+    }
+
+    /// Set song priority in a queue
+    pub fn prio<T: Into<Range>>(&mut self, pos: T, prio: u8) -> Ticket<()> {
+        self.enqueue(request::prio(pos.into(), prio))
+    }
+
+    /// Set song priority in a queue
+    pub fn prioid(&mut self, id: u32, prio: u8) -> Ticket<()> {
+        self.enqueue(request::prioid(id, prio))
+    }
+}
+
+/// Command list builder
+pub struct CommandList<'a, S: 'a + Read + Write> {
+    client: &'a mut Client<S>,
+    commands: Commands,
+}
+
+impl<'a, S: 'a + Read + Write> Deref for CommandList<'a, S> {
+    type Target = Commands;
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl<'a, S: 'a + Read + Write> DerefMut for CommandList<'a, S> {
+    fn deref_mut(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+impl<'a, S: 'a + Read + Write> CommandList<'a, S> {
+    pub(crate) fn new(client: &'a mut Client<S>) -> CommandList<'a, S> {
+        CommandList {
+            commands: Commands::new(client.version),
+            client,
+        }
+    }
+
+    /// Send all queued commands to the server and read their replies
+    ///
+    /// A failing command doesn't make this method fail, see `Replies` for details.
+    /// Neither does a broken line in a reply, it's returned as an error for its command.
+    pub fn run(mut self) -> Result<Replies, Error> {
+        let buf = wrap(self.commands.encoded()?);
+        self.client.write_raw(&buf)?;
+        read_replies(self.client)
+    }
+}
+
+/// Wrap encoded commands into a command list
+pub(crate) fn wrap(commands: &[u8]) -> Vec<u8> {
+    let mut buf = b"command_list_ok_begin\n".to_vec();
+    buf.extend_from_slice(commands);
+    buf.extend_from_slice(b"command_list_end\n");
+    buf
+}
+
+/// Read replies to all commands of a command list
+pub(crate) fn read_replies(source: &mut dyn Source) -> Result<Replies, Error> {
+    let limits = source.limits();
+    let mut replies = Vec::new();
+    let mut current = Vec::new();
+    // a broken line fails its command, but the rest of the response is still read
+    let mut broken = None;
+    loop {
+        match source.read_event() {
+            Ok(Event::ListOk) => replies.push(Some(match broken.take() {
+                Some(e) => Err(e),
+                None => Ok(mem::take(&mut current)),
+            })),
+            Ok(Event::Ok) => {
+                return Ok(Replies {
+                    replies,
+                    error: None,
+                    limits,
+                })
+            }
+            Ok(Event::Pair(a, b)) => current.push((a, b)),
+            Ok(Event::Ack(e)) => {
+                return Ok(Replies {
+                    replies,
+                    error: Some(e),
+                    limits,
+                })
+            }
+            Ok(Event::Greeting(_)) | Ok(Event::Binary(_)) => (),
+            Err(e) if e.is_recoverable() => {
+                current.clear();
+                broken.get_or_insert(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Replies to all commands of a command list
+pub struct Replies {
+    replies: Vec<Option<CommandReply>>,
+    error: Option<ServerError>,
+    limits: Limits,
+}
+
+impl Replies {
+    /// Take result of a command identified by its ticket
+    pub fn take<T>(&mut self, ticket: Ticket<T>) -> Result<T, Error> {
+        match self.replies.get_mut(ticket.index).and_then(Option::take) {
+            Some(Ok(pairs)) => ticket.parse(pairs, self.limits),
+            Some(Err(e)) => Err(e),
+            None => match self.error {
+                Some(ref e) if e.pos as usize == ticket.index => Err(Error::Server(e.clone())),
+                _ => Err(Error::Proto(ProtoError::Skipped)),
+            },
+        }
+    }
+
+    /// Error of the failed command, if any (its `pos` field is the failed command index)
+    pub fn error(&self) -> Option<&ServerError> {
+        self.error.as_ref()
+    }
+}
@@ -0,0 +1,227 @@
+//! The module defines connection options, applied to a new client connection
+//!
+//! ```rust,no_run
+//! # use mpd::ConnectOptions;
+//! # use std::time::Duration;
+//! let mut conn = ConnectOptions::new()
+//!     .connect_timeout(Duration::from_secs(3))
+//!     .read_timeout(Duration::from_secs(10))
+//!     .password("secret")
+//!     .tagtypes(&["Artist", "Album", "Title"])
+//!     .connect("127.0.0.1:6600")
+//!     .unwrap();
+//! ```
+
+#[cfg(feature = "tokio")]
+use crate::async_client::AsyncClient;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::request::{self, Request};
+use crate::stream::{self, Address, Stream};
+
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+
+/// Connection options builder
+///
+/// Password, binary limit, tag types and partition are set up right after the connection
+/// is established, in this order. Timeouts apply to the socket: a timed out connection attempt
+/// or read fails with `Error::Timeout`, and a timed out write fails with `Error::Io`.
+/// The connection is unusable after either of them.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    password: Option<String>,
+    binary_limit: Option<usize>,
+    tagtypes: Option<Vec<String>>,
+    partition: Option<String>,
+}
+
+impl ConnectOptions {
+    /// Create default options (no timeouts, no setup commands)
+    pub fn new() -> ConnectOptions {
+        ConnectOptions::default()
+    }
+
+    /// Set timeout for establishing TCP connection
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set timeout for reading each response from the server
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set timeout for writing each command to the server
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut ConnectOptions {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Set password to send after connection
+    pub fn password(&mut self, password: &str) -> &mut ConnectOptions {
+        self.password = Some(password.to_owned());
+        self
+    }
+
+    /// Set initial maximum binary response chunk size (see `Client::binarylimit()`)
+    pub fn binary_limit(&mut self, size: usize) -> &mut ConnectOptions {
+        self.binary_limit = Some(size);
+        self
+    }
+
+    /// Restrict tag types in responses to the given ones
+    pub fn tagtypes(&mut self, tags: &[&str]) -> &mut ConnectOptions {
+        self.tagtypes = Some(tags.iter().map(|&tag| tag.to_owned()).collect());
+        self
+    }
+
+    /// Set partition to switch to after connection
+    pub fn partition(&mut self, name: &str) -> &mut ConnectOptions {
+        self.partition = Some(name.to_owned());
+        self
+    }
+
+    /// Check if reads time out, so `WouldBlock` errors mean a timeout
+    pub(crate) fn has_read_timeout(&self) -> bool {
+        self.read_timeout.is_some()
+    }
+
+    /// Connect client to some IP address
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<Client<TcpStream>> {
+        let socket = self.connect_tcp(addr)?;
+        Client::with_options(socket, self)
+    }
+
+    /// Connect client to a Unix domain socket
+    ///
+    /// Path starting with `@` (like `@mpd`) denotes a Linux abstract socket.
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(&self, path: P) -> Result<Client<UnixStream>> {
+        let socket = self.connect_unix_socket(path.as_ref())?;
+        Client::with_options(socket, self)
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn connect_unix_socket(&self, path: &Path) -> Result<UnixStream> {
+        let socket = stream::connect_unix(path)?;
+        socket.set_read_timeout(self.read_timeout)?;
+        socket.set_write_timeout(self.write_timeout)?;
+        Ok(socket)
+    }
+
+    /// Connect client as configured by environment (see `Client::from_env()`)
+    ///
+    /// A password from `MPD_HOST` is used, unless it's set in these options.
+    pub fn from_env(&self) -> Result<Client<Stream>> {
+        let (addresses, password) = stream::env_addresses()?;
+        let mut options = self.clone();
+        if options.password.is_none() {
+            options.password = password;
+        }
+
+        let mut error = None;
+        for address in addresses {
+            match options.connect_address(&address) {
+                Ok(socket) => return Client::with_options(socket, &options),
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| Error::Io(io::Error::new(io::ErrorKind::NotFound, "no address to connect to"))))
+    }
+
+    pub(crate) fn connect_tcp<A: ToSocketAddrs>(&self, addr: A) -> Result<TcpStream> {
+        let socket = match self.connect_timeout {
+            None => TcpStream::connect(addr)?,
+            Some(timeout) => {
+                let mut result = Err(io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address"));
+                for addr in addr.to_socket_addrs()? {
+                    result = TcpStream::connect_timeout(&addr, timeout);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result.map_err(Error::timed_out)?
+            }
+        };
+        socket.set_read_timeout(self.read_timeout)?;
+        socket.set_write_timeout(self.write_timeout)?;
+        Ok(socket)
+    }
+
+    fn connect_address(&self, address: &Address) -> Result<Stream> {
+        match *address {
+            Address::Tcp(ref host, port) => self.connect_tcp((&**host, port)).map(Stream::Tcp),
+            #[cfg(unix)]
+            Address::Unix(ref path) => self.connect_unix_socket(path).map(Stream::Unix),
+            #[cfg(not(unix))]
+            Address::Unix(_) => Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported"))),
+        }
+    }
+
+    /// Requests to set up a freshly connected client, in order
+    pub(crate) fn setup(&self) -> Vec<Result<Request<()>>> {
+        let mut requests = Vec::new();
+        if let Some(ref password) = self.password {
+            requests.push(request::login(password));
+        }
+        if let Some(size) = self.binary_limit {
+            requests.push(request::binarylimit(size));
+        }
+        if let Some(ref tags) = self.tagtypes {
+            requests.push(request::tagtypes_set("tagtypes clear", &[]));
+            if !tags.is_empty() {
+                requests.push(request::tagtypes_set("tagtypes enable", &tags.iter().map(|tag| &**tag).collect::<Vec<_>>()));
+            }
+        }
+        if let Some(ref name) = self.partition {
+            requests.push(request::partition(name));
+        }
+        requests
+    }
+
+    /// Binary limit set up by `setup()` requests, if any
+    pub(crate) fn initial_binary_limit(&self) -> Option<usize> {
+        self.binary_limit
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ConnectOptions {
+    /// Connect asynchronous client to some IP address
+    ///
+    /// Read and write timeouts are not applied to asynchronous sockets,
+    /// wrap futures with `tokio::time::timeout()` instead.
+    pub async fn connect_async<A: tokio::net::ToSocketAddrs>(&self, addr: A) -> Result<AsyncClient<tokio::net::TcpStream>> {
+        let socket = tokio::net::TcpStream::connect(addr).await?;
+        AsyncClient::with_options(socket, self).await
+    }
+
+    /// Connect asynchronous client to a Unix domain socket
+    ///
+    /// Path starting with `@` (like `@mpd`) denotes a Linux abstract socket.
+    /// Timeouts are not applied, as for `connect_async()`.
+    #[cfg(unix)]
+    pub async fn connect_unix_async<P: AsRef<Path>>(&self, path: P) -> Result<AsyncClient<tokio::net::UnixStream>> {
+        let path = path.as_ref();
+        let socket = if path.to_string_lossy().starts_with('@') {
+            // Connecting to a local socket doesn't block for long
+            let socket = stream::connect_unix(path)?;
+            socket.set_nonblocking(true)?;
+            tokio::net::UnixStream::from_std(socket)?
+        } else {
+            tokio::net::UnixStream::connect(path).await?
+        };
+        AsyncClient::with_options(socket, self).await
+    }
+}
@@ -12,13 +12,15 @@
 //!
 //! This module defines all necessary infrastructure to represent these kinds or errors.
 
+use crate::version::Version;
+
 use std::convert::From;
 use std::error::Error as StdError;
 use std::fmt;
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
 use std::num::{ParseFloatError, ParseIntError};
 use std::result;
-use std::str::FromStr;
+use std::str::{FromStr, Utf8Error};
 use std::string::ParseError as StringParseError;
 
 // Server errors {{{
@@ -126,33 +128,19 @@ impl FromStr for ServerError {
     type Err = ParseError;
     fn from_str(s: &str) -> result::Result<ServerError, ParseError> {
         // ACK [<code>@<index>] {<command>} <description>
-        if let Some(s) = s.strip_prefix("ACK [") {
-            if let (Some(atsign), Some(right_bracket)) = (s.find('@'), s.find(']')) {
-                match (s[..atsign].parse(), s[atsign + 1..right_bracket].parse()) {
-                    (Ok(code), Ok(pos)) => {
-                        let s = &s[right_bracket + 1..];
-                        if let (Some(left_brace), Some(right_brace)) = (s.find('{'), s.find('}')) {
-                            let command = s[left_brace + 1..right_brace].to_string();
-                            let detail = s[right_brace + 1..].trim().to_string();
-                            Ok(ServerError {
-                                code,
-                                pos,
-                                command,
-                                detail,
-                            })
-                        } else {
-                            Err(ParseError::NoMessage)
-                        }
-                    }
-                    (Err(_), _) => Err(ParseError::BadCode),
-                    (_, Err(_)) => Err(ParseError::BadPos),
-                }
-            } else {
-                Err(ParseError::NoCodePos)
-            }
-        } else {
-            Err(ParseError::NotAck)
-        }
+        let s = s.strip_prefix("ACK [").ok_or(ParseError::NotAck)?;
+        let (codepos, s) = s.split_once(']').ok_or(ParseError::NoCodePos)?;
+        let (code, pos) = codepos.split_once('@').ok_or(ParseError::NoCodePos)?;
+        let code = code.parse().map_err(|_| ParseError::BadCode)?;
+        let pos = pos.parse().map_err(|_| ParseError::BadPos)?;
+        let (_, s) = s.split_once('{').ok_or(ParseError::NoMessage)?;
+        let (command, detail) = s.split_once('}').ok_or(ParseError::NoMessage)?;
+        Ok(ServerError {
+            code,
+            pos,
+            command: command.to_string(),
+            detail: detail.trim().to_string(),
+        })
     }
 }
 // }}}
@@ -169,11 +157,49 @@ pub enum Error {
     Proto(ProtoError),
     /// server errors (a.k.a. `ACK` responses from server)
     Server(ServerError),
+    /// connection attempt or response read timed out
+    ///
+    /// The rest of the response may still arrive, so the connection is unusable afterwards
+    /// and should be closed (like after `Io` errors).
+    Timeout,
+    /// invalid command name or argument (e.g. containing a newline), rejected before sending it to server
+    BadArgument(String),
+    /// command (or some of its arguments) is not supported by the server protocol version
+    Unsupported {
+        /// command name
+        command: String,
+        /// minimum required protocol version
+        required: Version,
+        /// server protocol version
+        actual: Version,
+    },
 }
 
 /// Shortcut type for MPD results
 pub type Result<T> = result::Result<T, Error>;
 
+impl Error {
+    /// Check if the error was caused by a broken line (or binary chunk) of a response,
+    /// which was skipped, so the rest of the response can still be read
+    pub(crate) fn is_recoverable(&self) -> bool {
+        matches!(*self, Error::Parse(_) | Error::Proto(_))
+    }
+
+    /// Check if the connection is unusable after the error
+    pub(crate) fn is_broken(&self) -> bool {
+        matches!(*self, Error::Io(_) | Error::Timeout)
+    }
+
+    /// Convert an error of a socket operation with a timeout set, a timed out one becomes `Timeout`
+    pub(crate) fn timed_out(e: IoError) -> Error {
+        match e.kind() {
+            // Socket read timeouts are reported as `WouldBlock` on Unix
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Error::Timeout,
+            _ => Error::Io(e),
+        }
+    }
+}
+
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
@@ -181,6 +207,7 @@ impl StdError for Error {
             Error::Parse(ref err) => Some(err),
             Error::Proto(ref err) => Some(err),
             Error::Server(ref err) => Some(err),
+            Error::Timeout | Error::BadArgument(_) | Error::Unsupported { .. } => None,
         }
     }
 }
@@ -192,6 +219,13 @@ impl fmt::Display for Error {
             Error::Parse(ref err) => err.fmt(f),
             Error::Proto(ref err) => err.fmt(f),
             Error::Server(ref err) => err.fmt(f),
+            Error::Timeout => write!(f, "timed out"),
+            Error::BadArgument(ref arg) => write!(f, "invalid argument: {:?}", arg),
+            Error::Unsupported {
+                ref command,
+                ref required,
+                ref actual,
+            } => write!(f, "`{}' requires protocol version {}, server has {}", command, required, actual),
         }
     }
 }
@@ -272,6 +306,8 @@ pub enum ParseError {
     BadState(String),
     /// unknown error code in `ACK` response
     BadErrorCode(usize),
+    /// response line is not valid UTF-8 (and lossy decoding is off)
+    BadUtf8(Utf8Error),
 }
 
 impl StdError for ParseError {}
@@ -299,6 +335,7 @@ impl fmt::Display for ParseError {
             BadChans(_) => "invalid audio format channels",
             BadState(_) => "invalid playing state",
             BadErrorCode(_) => "unknown error code",
+            BadUtf8(_) => "invalid UTF-8",
         };
 
         write!(f, "{}", desc)
@@ -342,6 +379,12 @@ pub enum ProtoError {
     NoField(&'static str),
     /// expected sticker value, but didn't find it
     BadSticker,
+    /// binary data length doesn't match the announced one
+    BadBinary,
+    /// command wasn't executed, because a previous command in a command list failed
+    Skipped,
+    /// response exceeds one of the client limits (`line`, `records` or `binary`)
+    TooLarge(&'static str),
 }
 
 impl StdError for ProtoError {}
@@ -354,6 +397,9 @@ impl fmt::Display for ProtoError {
             ProtoError::BadBanner => "banner error",
             ProtoError::NoField(_) => "missing field",
             ProtoError::BadSticker => "sticker error",
+            ProtoError::BadBinary => "binary data length mismatch",
+            ProtoError::Skipped => "command skipped",
+            ProtoError::TooLarge(_) => "response too large",
         };
 
         write!(f, "{}", desc)
@@ -28,12 +28,14 @@
 
 use crate::client::Client;
 use crate::error::{Error, ParseError};
-use crate::proto::Proto;
+use crate::proto::{Proto, Reader};
+use crate::stream::ReadTimeout;
 
 use std::fmt;
 use std::io::{Read, Write};
 use std::mem::forget;
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Subsystems for `idle` command
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -125,18 +127,54 @@ pub struct IdleGuard<'a, S: 'a + Read + Write>(&'a mut Client<S>);
 impl<'a, S: 'a + Read + Write> IdleGuard<'a, S> {
     /// Get list of subsystems with new events, interrupting idle mode in process
     pub fn get(self) -> Result<Vec<Subsystem>, Error> {
+        let result = self.0.read_list("changed").map(parse_changed);
+        self.0.idling = false;
+        forget(self);
+        result
+    }
+
+    /// Wait for events at most for a given time, then interrupt idle mode with `noidle`
+    ///
+    /// On timeout, an empty list is returned, unless some events have just
+    /// happened before `noidle` was received by the server.
+    pub fn get_timeout(self, timeout: Duration) -> Result<Vec<Subsystem>, Error>
+    where
+        S: ReadTimeout,
+    {
+        if self.0.poll_read(timeout)? {
+            self.get()
+        } else {
+            self.cancel()
+        }
+    }
+
+    /// Interrupt idle mode with `noidle` without waiting for events
+    ///
+    /// Unlike dropping the guard, this reports errors, and returns events which
+    /// happened since `idle` command (if any).
+    pub fn cancel(self) -> Result<Vec<Subsystem>, Error> {
         let result = self
             .0
-            .read_list("changed")
-            .and_then(|v| v.into_iter().map(|b| b.parse().map_err(From::from)).collect());
+            .run_command("noidle", ())
+            .and_then(|_| self.0.read_list("changed"))
+            .map(parse_changed);
+        self.0.idling = false;
         forget(self);
         result
     }
 }
 
+/// Parse names of changed subsystems, skipping the ones unknown to this library
+///
+/// Newer servers add subsystems, and a reply shouldn't be lost because of them.
+pub(crate) fn parse_changed(changed: Vec<String>) -> Vec<Subsystem> {
+    changed.iter().filter_map(|s| s.parse().ok()).collect()
+}
+
 impl<'a, S: 'a + Read + Write> Drop for IdleGuard<'a, S> {
     fn drop(&mut self) {
         let _ = self.0.run_command("noidle", ()).map(|_| self.0.drain());
+        self.0.idling = false;
     }
 }
 
@@ -159,7 +197,7 @@ pub trait Idle {
     /// and releases client object.
     ///
     /// If the guard goes out of scope, wait lock is released as well, but all queued events
-    /// will be silently ignored.
+    /// will be silently ignored. Use `.cancel()` to stop waiting and get errors.
     fn idle<'a>(&'a mut self, subsystems: &[Subsystem]) -> Result<IdleGuard<'a, Self::Stream>, Error>;
 
     /// Wait for events from a set of subsystems and return list of affected subsystems
@@ -169,12 +207,72 @@ pub trait Idle {
     fn wait(&mut self, subsystems: &[Subsystem]) -> Result<Vec<Subsystem>, Error> {
         self.idle(subsystems).and_then(IdleGuard::get)
     }
+
+    /// Wait for events from a set of subsystems at most for a given time
+    ///
+    /// Returns an empty list on timeout, so it can be called in a loop
+    /// to wake up periodically (e.g. to refresh elapsed time display).
+    fn wait_timeout(&mut self, subsystems: &[Subsystem], timeout: Duration) -> Result<Vec<Subsystem>, Error>
+    where
+        Self::Stream: ReadTimeout,
+    {
+        self.idle(subsystems).and_then(|guard| guard.get_timeout(timeout))
+    }
 }
 
 impl<S: Read + Write> Idle for Client<S> {
     type Stream = S;
     fn idle<'a>(&'a mut self, subsystems: &[Subsystem]) -> Result<IdleGuard<'a, S>, Error> {
         self.run_command("idle", subsystems)?;
+        self.idling = true;
         Ok(IdleGuard(self))
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{Idle, Subsystem};
+    use crate::client::Client;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_timeout() {
+        let (socket, mut server) = UnixStream::pair().unwrap();
+        server.write_all(b"OK MPD 0.23.0\nchanged: mixer\nOK\n").unwrap();
+        let mut mpd = Client::new(socket).unwrap();
+        let timeout = Duration::from_millis(50);
+
+        // An event is received in time
+        assert_eq!(mpd.wait_timeout(&[], timeout).unwrap(), vec![Subsystem::Mixer]);
+
+        // Nothing happens, `noidle` is sent, and the read timeout is restored
+        let fake = thread::spawn(move || {
+            let mut lines = BufReader::new(server.try_clone().unwrap()).lines().map(Result::unwrap);
+            assert_eq!(lines.next().unwrap(), "idle");
+            assert_eq!(lines.next().unwrap(), "idle \"player\"");
+            assert_eq!(lines.next().unwrap(), "noidle");
+            server.write_all(b"OK\n").unwrap();
+            assert_eq!(lines.next().unwrap(), "idle");
+            assert_eq!(lines.next().unwrap(), "noidle");
+            server.write_all(b"changed: options\nOK\n").unwrap();
+            assert_eq!(lines.next().unwrap(), "idle");
+            assert_eq!(lines.next().unwrap(), "noidle");
+            server.write_all(b"changed: unknown\nchanged: player\nOK\n").unwrap();
+            assert_eq!(lines.next().unwrap(), "idle");
+            assert_eq!(lines.next().unwrap(), "noidle");
+            server.write_all(b"changed\nOK\n").unwrap();
+        });
+        assert!(mpd.wait_timeout(&[Subsystem::Player], timeout).unwrap().is_empty());
+        assert_eq!(mpd.socket().read_timeout().unwrap(), None);
+
+        // Explicit cancellation returns queued events
+        assert_eq!(mpd.idle(&[]).unwrap().cancel().unwrap(), vec![Subsystem::Options]);
+        // Unknown subsystems are skipped
+        assert_eq!(mpd.idle(&[]).unwrap().cancel().unwrap(), vec![Subsystem::Player]);
+        assert!(mpd.idle(&[]).unwrap().cancel().is_err());
+        fake.join().unwrap();
+    }
+}
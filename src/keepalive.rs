@@ -0,0 +1,107 @@
+//! The module defines a keepalive thread, which pings MPD on a shared connection
+//!
+//! MPD closes connections, which stay silent for longer than its `connection_timeout`
+//! (60 seconds by default). A long living client can be protected from this:
+//!
+//! ```rust,no_run
+//! # use mpd::{Client, KeepAlive};
+//! # use std::sync::{Arc, Mutex};
+//! # use std::time::Duration;
+//! let conn = Arc::new(Mutex::new(Client::connect("127.0.0.1:6600").unwrap()));
+//! let keepalive = KeepAlive::spawn(conn.clone(), Duration::from_secs(30));
+//! // ... use `conn.lock()` to run commands ...
+//! if let Some(e) = keepalive.stop() {
+//!     println!("keepalive failed: {}", e);
+//! }
+//! ```
+//!
+//! The thread never blocks on the client mutex: if the client is locked (e.g. it runs
+//! a command or waits in idle mode holding the lock), the ping is skipped until
+//! the next check. See also `Client::keepalive()` to ping from your own event loop.
+
+use crate::client::Client;
+use crate::error::Error;
+
+use std::io::{Read, Write};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Shortest period between pings, shorter ones would make the thread spin
+const MIN_PERIOD: Duration = Duration::from_secs(1);
+
+/// Keepalive thread handle, the thread is stopped when it's dropped
+#[derive(Debug)]
+pub struct KeepAlive {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Option<Error>>>,
+}
+
+impl KeepAlive {
+    /// Spawn a thread, which sends `ping` when nothing was sent to the server for a given period
+    ///
+    /// The thread stops on the first failed ping.
+    /// Periods shorter than a second are rounded up to one second.
+    pub fn spawn<S>(client: Arc<Mutex<Client<S>>>, period: Duration) -> KeepAlive
+    where
+        S: Read + Write + Send + 'static,
+    {
+        let period = period.max(MIN_PERIOD);
+        let (stop, stopped) = channel();
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(period / 2) {
+                Err(RecvTimeoutError::Timeout) => (),
+                _ => return None,
+            }
+            match client.try_lock() {
+                Ok(mut client) => {
+                    if let Err(e) = client.keepalive(period) {
+                        return Some(e);
+                    }
+                }
+                Err(TryLockError::WouldBlock) => (),
+                Err(TryLockError::Poisoned(_)) => return None,
+            }
+        });
+        KeepAlive {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop the thread, and return the error it stopped with, if any
+    pub fn stop(mut self) -> Option<Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Option<Error> {
+        self.stop.take();
+        self.thread.take().and_then(|thread| thread.join().ok()).flatten()
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeepAlive;
+    use crate::client::Client;
+    use crate::test_util::Mock;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn zero_period() {
+        let client = Arc::new(Mutex::new(Client::new(Mock::new(b"OK MPD 0.23.0\n")).unwrap()));
+        let keepalive = KeepAlive::spawn(client.clone(), Duration::ZERO);
+        thread::sleep(Duration::from_millis(50));
+        assert!(keepalive.stop().is_none());
+        assert_eq!(client.lock().unwrap().socket().written(), "");
+    }
+}
@@ -32,35 +32,64 @@
 //! # }
 //! ```
 
+#[cfg(feature = "tokio")]
+pub mod async_client;
+pub mod command_list;
+pub mod connect;
 mod convert;
 pub mod error;
 pub mod idle;
+pub mod keepalive;
+pub mod list;
 pub mod lsinfo;
 pub mod message;
 pub mod mount;
 pub mod output;
+pub mod picture;
+pub mod pipeline;
 pub mod playlist;
 pub mod plugin;
+pub mod reconnect;
 pub mod reply;
 pub mod search;
+pub mod shared;
 pub mod song;
 pub mod stats;
 pub mod status;
 mod sticker;
+pub mod stream;
 pub mod version;
+pub mod watch;
 
 pub mod client;
+pub mod codec;
 mod proto;
+mod request;
+#[cfg(test)]
+mod test_util;
 
-pub use client::Client;
+#[cfg(feature = "tokio")]
+pub use async_client::AsyncClient;
+pub use client::{Client, Limits, LossyLine};
+pub use command_list::CommandList;
+pub use connect::ConnectOptions;
 pub use idle::{Idle, Subsystem};
+pub use keepalive::KeepAlive;
+pub use list::ListGroup;
 pub use message::{Channel, Message};
 pub use mount::{Mount, Neighbor};
 pub use output::Output;
+pub use picture::Picture;
+pub use pipeline::Pipeline;
 pub use playlist::Playlist;
 pub use plugin::Plugin;
-pub use search::{FilterQuery, Query, Term};
-pub use song::Song;
-pub use stats::Stats;
+pub use reconnect::ReconnectingClient;
+pub use reply::Response;
+pub use search::{Expression, FilterQuery, Query, Sort, SortKey, Term, Window};
+pub use shared::SharedClient;
+pub use song::{Position, Song};
+pub use stats::{Count, Stats};
 pub use status::{ReplayGain, State, Status};
+pub use stream::Stream;
 pub use version::Version;
+pub use watch::Watcher;
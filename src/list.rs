@@ -0,0 +1,115 @@
+//! The module defines the response from a grouped list command.
+
+use crate::error::{Error, ProtoError};
+
+/// One group of a grouped `list` response
+///
+/// Groups nest in the order MPD emits them, e.g. `list album group albumartist group date`
+/// yields album artist groups containing date groups containing album names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListGroup {
+    /// group tag name, as emitted by the server (e.g. `AlbumArtist`)
+    pub tag: String,
+    /// group tag value
+    pub value: String,
+    /// nested groups
+    pub groups: Vec<ListGroup>,
+    /// listed tag values in this group
+    pub values: Vec<String>,
+}
+
+impl ListGroup {
+    /// Build group tree from `list` response pairs, `term` being the listed tag name
+    pub(crate) fn from_pairs<I>(term: &str, iter: I) -> Result<Vec<ListGroup>, Error>
+    where
+        I: Iterator<Item = Result<(String, String), Error>>,
+    {
+        let mut result = Vec::new();
+        // group tags in nesting order, as they first appear in the response
+        let mut tags: Vec<String> = Vec::new();
+        let mut depth: usize = 0;
+
+        for res in iter {
+            let (key, value) = res?;
+            if key.eq_ignore_ascii_case(term) {
+                match depth.checked_sub(1).and_then(|level| Self::level(&mut result, level).last_mut()) {
+                    Some(group) => group.values.push(value),
+                    None => return Err(Error::Proto(ProtoError::NoField("group"))),
+                }
+            } else {
+                let level = match tags.iter().position(|t| *t == key) {
+                    Some(level) => level.min(depth),
+                    None => {
+                        tags.push(key.clone());
+                        depth
+                    }
+                };
+                Self::level(&mut result, level).push(ListGroup {
+                    tag: key,
+                    value,
+                    groups: Vec::new(),
+                    values: Vec::new(),
+                });
+                depth = level + 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Get list of groups at a given nesting level, following the last group on each level
+    fn level(groups: &mut Vec<ListGroup>, level: usize) -> &mut Vec<ListGroup> {
+        if level == 0 || groups.is_empty() {
+            return groups;
+        }
+        let last = groups.len() - 1;
+        Self::level(&mut groups[last].groups, level - 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(input: &[(&str, &str)]) -> impl Iterator<Item = Result<(String, String), Error>> {
+        input
+            .iter()
+            .map(|&(a, b)| Ok((a.to_owned(), b.to_owned())))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn nested_groups() {
+        let groups = ListGroup::from_pairs(
+            "album",
+            pairs(&[
+                ("AlbumArtist", "A"),
+                ("Date", "2000"),
+                ("Album", "X"),
+                ("Album", "Y"),
+                ("Date", "2001"),
+                ("Album", "Z"),
+                ("AlbumArtist", "B"),
+                ("Date", ""),
+                ("Album", "W"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!((&*groups[0].tag, &*groups[0].value), ("AlbumArtist", "A"));
+        assert_eq!(groups[0].groups.len(), 2);
+        assert_eq!(groups[0].groups[0].value, "2000");
+        assert_eq!(groups[0].groups[0].values, vec!["X", "Y"]);
+        assert_eq!(groups[0].groups[1].values, vec!["Z"]);
+        assert_eq!(groups[1].value, "B");
+        assert_eq!(groups[1].groups[0].value, "");
+        assert_eq!(groups[1].groups[0].values, vec!["W"]);
+    }
+
+    #[test]
+    fn ungrouped_values() {
+        assert!(ListGroup::from_pairs("album", pairs(&[("Album", "X")])).is_err());
+    }
+}
@@ -1,9 +1,13 @@
 //! The module defines the response from an lsinfo command.
 
+use crate::client::Client;
 use crate::convert::FromIter;
 use crate::error::Error;
+use crate::proto::{Events, Pairs};
 use crate::song::Song;
 
+use std::io::{Read, Write};
+
 #[derive(Debug)]
 /// Response form the lsinfo command. Contains either a song, a directory, or a playlist.
 pub enum LsInfoResponse {
@@ -44,3 +48,81 @@ impl FromIter for LsInfoResponse {
         }
     }
 }
+
+/// Lazy iterator over `listall`/`listallinfo` responses
+///
+/// Records are parsed one at a time as they are read from the connection.
+/// If the iterator is dropped before it is exhausted, the rest of the response
+/// is read and discarded, so the client can be used again.
+pub struct LsInfoIter<'a, S: 'a + Read + Write> {
+    pairs: Pairs<Events<'a, Client<S>>>,
+    next: Option<(String, String)>,
+    done: bool,
+}
+
+impl<'a, S: 'a + Read + Write> LsInfoIter<'a, S> {
+    pub(crate) fn new(pairs: Pairs<Events<'a, Client<S>>>) -> LsInfoIter<'a, S> {
+        LsInfoIter {
+            pairs,
+            next: None,
+            done: false,
+        }
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Iterator for LsInfoIter<'a, S> {
+    type Item = Result<LsInfoResponse, Error>;
+
+    fn next(&mut self) -> Option<Result<LsInfoResponse, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let mut items = Vec::new();
+        if let Some(pair) = self.next.take() {
+            items.push(pair);
+        }
+
+        loop {
+            match self.pairs.next() {
+                Some(Ok((a, b))) => {
+                    if !items.is_empty() && (a == "directory" || a == "file" || a == "playlist") {
+                        self.next = Some((a, b));
+                        break;
+                    }
+                    items.push((a, b));
+                }
+                Some(Err(e)) => {
+                    // a broken line doesn't break the rest of the response,
+                    // but `ACK` ends it, and after IO errors there's nothing to read
+                    if !e.is_recoverable() {
+                        self.done = true;
+                    }
+                    return Some(Err(e));
+                }
+                None => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        if items.is_empty() {
+            None
+        } else {
+            Some(LsInfoResponse::from_iter(items.into_iter().map(Ok)))
+        }
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Drop for LsInfoIter<'a, S> {
+    fn drop(&mut self) {
+        while !self.done {
+            match self.pairs.next() {
+                Some(Ok(_)) => (),
+                Some(Err(ref e)) if e.is_recoverable() => (),
+                _ => self.done = true,
+            }
+        }
+    }
+}
@@ -70,16 +70,26 @@ impl Channel {
         Channel(name)
     }
 
+    /// Check the name validity before sending it to server (it may be created with `new_unchecked()`)
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if Channel::is_valid_name(&self.0) {
+            Ok(())
+        } else {
+            Err(Error::BadArgument(self.0.clone()))
+        }
+    }
+
     /// Check if given name is a valid channel name
     ///
-    /// Valid channel name can contain only English letters (`A`-`Z`, `a`-`z`),
+    /// Valid channel name is not empty and can contain only English letters (`A`-`Z`, `a`-`z`),
     /// numbers (`0`-`9`), underscore, forward slash, dot and colon (`_`, `/`, `.`, `:`)
     pub fn is_valid_name(name: &str) -> bool {
-        name.bytes().all(|b| {
-            (0x61..=0x7a).contains(&b)
-                || (0x41..=0x5a).contains(&b)
-                || (0x30..=0x39).contains(&b)
-                || (b == 0x5f || b == 0x2f || b == 0x2e || b == 0x3a)
-        })
+        !name.is_empty()
+            && name.bytes().all(|b| {
+                (0x61..=0x7a).contains(&b)
+                    || (0x41..=0x5a).contains(&b)
+                    || (0x30..=0x39).contains(&b)
+                    || (b == 0x5f || b == 0x2f || b == 0x2e || b == 0x3a)
+            })
     }
 }
@@ -0,0 +1,19 @@
+//! The module defines cover art data structures.
+
+/// Cover art picture, as returned by `readpicture` and `albumart` commands
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Picture {
+    /// raw picture data
+    pub data: Vec<u8>,
+    /// picture MIME type, if reported by the server (never set for `albumart`)
+    pub mime: Option<String>,
+}
+
+/// Summary of a picture transfer streamed into a writer
+#[derive(Debug, Clone, PartialEq)]
+pub struct PictureInfo {
+    /// total picture size in bytes
+    pub size: usize,
+    /// picture MIME type, if reported by the server (never set for `albumart`)
+    pub mime: Option<String>,
+}
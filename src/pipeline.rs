@@ -0,0 +1,153 @@
+//! The module defines command pipelines
+//!
+//! A pipeline writes several commands to the server at once, and reads their replies
+//! later, in order, only when they are requested. Unlike a
+//! [`CommandList`](../command_list/struct.CommandList.html), every command succeeds or fails
+//! on its own, and the client can keep sending commands while earlier replies are still in flight.
+//!
+//! ```rust,no_run
+//! # use mpd::Client;
+//! let mut conn = Client::connect("127.0.0.1:6600").unwrap();
+//! let mut pipeline = conn.pipeline();
+//! let status = pipeline.status();
+//! let queue = pipeline.queue();
+//! let mut pending = pipeline.send().unwrap();
+//! // ...do something else while the server is busy...
+//! println!("{:?}", pending.take(status));
+//! println!("{:?}", pending.take(queue));
+//! ```
+
+use crate::client::Client;
+use crate::codec::Event;
+use crate::command_list::{CommandReply, Commands, Ticket};
+use crate::error::{Error, ProtoError};
+use crate::proto::{Proto, Source};
+
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+/// Pipeline builder
+pub struct Pipeline<'a, S: 'a + Read + Write> {
+    client: &'a mut Client<S>,
+    commands: Commands,
+}
+
+impl<'a, S: 'a + Read + Write> Deref for Pipeline<'a, S> {
+    type Target = Commands;
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl<'a, S: 'a + Read + Write> DerefMut for Pipeline<'a, S> {
+    fn deref_mut(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Pipeline<'a, S> {
+    pub(crate) fn new(client: &'a mut Client<S>) -> Pipeline<'a, S> {
+        Pipeline {
+            commands: Commands::new(client.version),
+            client,
+        }
+    }
+
+    /// Write all queued commands to the server without waiting for replies
+    pub fn send(mut self) -> Result<Pending<'a, S>, Error> {
+        self.client.write_raw(self.commands.encoded()?)?;
+        Ok(Pending {
+            client: self.client,
+            count: self.commands.len(),
+            replies: Vec::new(),
+            broken: false,
+        })
+    }
+}
+
+/// Replies to a sent pipeline, read from the connection on demand
+///
+/// If dropped before all replies are taken, the rest of replies is read and discarded,
+/// so the client can be used again.
+pub struct Pending<'a, S: 'a + Read + Write> {
+    client: &'a mut Client<S>,
+    count: usize,
+    replies: Vec<Option<CommandReply>>,
+    broken: bool,
+}
+
+impl<'a, S: 'a + Read + Write> Pending<'a, S> {
+    /// Take result of a command identified by its ticket, reading replies up to it if needed
+    pub fn take<T>(&mut self, ticket: Ticket<T>) -> Result<T, Error> {
+        while self.replies.len() <= ticket.index() && self.replies.len() < self.count {
+            let reply = self.read_reply()?;
+            self.replies.push(Some(reply));
+        }
+        match self.replies.get_mut(ticket.index()).and_then(Option::take) {
+            Some(Ok(pairs)) => ticket.parse(pairs, self.client.limits()),
+            Some(Err(e)) => Err(e),
+            None => Err(Error::Proto(ProtoError::Skipped)),
+        }
+    }
+
+    /// Read all remaining replies, returning the first error of replies not taken yet, if any
+    ///
+    /// This includes both server errors (`ACK`) and broken replies.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.read_all()?;
+        match self.replies.iter_mut().find_map(|r| r.take().and_then(Result::err)) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn read_all(&mut self) -> Result<(), Error> {
+        while self.replies.len() < self.count {
+            let reply = self.read_reply()?;
+            self.replies.push(Some(reply));
+        }
+        Ok(())
+    }
+
+    fn read_reply(&mut self) -> Result<CommandReply, Error> {
+        if self.broken {
+            return Err(Error::Proto(ProtoError::Skipped));
+        }
+        let reply = read_reply(self.client);
+        // replies are out of sync with commands from now on
+        self.broken = reply.is_err();
+        reply
+    }
+}
+
+/// Read a reply to a single command of a pipeline
+///
+/// A broken line fails its command, but the rest of the reply is still read.
+/// Only an error breaking the connection is returned as the outer error.
+pub(crate) fn read_reply(source: &mut dyn Source) -> Result<CommandReply, Error> {
+    let mut pairs = Vec::new();
+    let mut error = None;
+    loop {
+        match source.read_event() {
+            Ok(Event::Pair(a, b)) => pairs.push((a, b)),
+            Ok(Event::Ok) | Ok(Event::ListOk) => {
+                return Ok(match error {
+                    Some(e) => Err(e),
+                    None => Ok(pairs),
+                })
+            }
+            Ok(Event::Ack(e)) => return Ok(Err(Error::Server(e))),
+            Ok(Event::Greeting(_)) | Ok(Event::Binary(_)) => (),
+            Err(e) if e.is_recoverable() => {
+                error.get_or_insert(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Drop for Pending<'a, S> {
+    fn drop(&mut self) {
+        let _ = self.read_all();
+    }
+}
@@ -42,7 +42,7 @@ impl FromIter for Vec<Plugin> {
                         p.suffixes.push(b);
                     }
                 }
-                _ => unreachable!(),
+                _ => (),
             }
         }
         if let Some(p) = plugin {
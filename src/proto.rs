@@ -1,34 +1,35 @@
 // Hidden internal interface
 #![allow(missing_docs)]
 
-use bufstream::BufStream;
-
+use crate::client::Limits;
+use crate::codec::{encode_command, Event};
 use crate::convert::FromIter;
 use crate::error::{Error, ParseError, ProtoError, Result};
-use crate::reply::Reply;
 
 use std::fmt;
-use std::io::{self, Lines, Read, Write};
+use std::io::{self, Read, Write};
 use std::result::Result as StdResult;
 use std::str::FromStr;
+use std::vec;
 
 pub struct Pairs<I>(pub I);
 
 impl<I> Iterator for Pairs<I>
 where
-    I: Iterator<Item = io::Result<String>>,
+    I: Iterator<Item = Result<Event>>,
 {
     type Item = Result<(String, String)>;
     fn next(&mut self) -> Option<Result<(String, String)>> {
-        let reply: Option<Result<Reply>> = self
-            .0
-            .next()
-            .map(|v| v.map_err(Error::Io).and_then(|s| s.parse::<Reply>().map_err(Error::Parse)));
-        match reply {
-            Some(Ok(Reply::Pair(a, b))) => Some(Ok((a, b))),
-            None | Some(Ok(Reply::Ok)) => None,
-            Some(Ok(Reply::Ack(e))) => Some(Err(Error::Server(e))),
-            Some(Err(e)) => Some(Err(e)),
+        loop {
+            return match self.0.next() {
+                Some(Ok(Event::Pair(a, b))) => Some(Ok((a, b))),
+                None | Some(Ok(Event::Ok)) | Some(Ok(Event::ListOk)) => None,
+                Some(Ok(Event::Ack(e))) => Some(Err(Error::Server(e))),
+                // Binary data is only expected by dedicated methods
+                Some(Ok(Event::Binary(_))) => continue,
+                Some(Ok(Event::Greeting(_))) => Some(Err(Error::Proto(ProtoError::NotPair))),
+                Some(Err(e)) => Some(Err(e)),
+            };
         }
     }
 }
@@ -49,17 +50,29 @@ impl Separator for &[&str] {
     }
 }
 
-pub struct Maps<'a, I: 'a, S: Separator> {
-    pairs: &'a mut Pairs<I>,
+pub struct Maps<'a, P: 'a, S: Separator> {
+    pairs: &'a mut P,
     sep: S,
     value: Option<(String, String)>,
     done: bool,
     first: bool,
 }
 
-impl<'a, I, S: Separator> Iterator for Maps<'a, I, S>
+impl<'a, P: 'a, S: Separator> Maps<'a, P, S> {
+    pub fn new(pairs: &'a mut P, sep: S) -> Maps<'a, P, S> {
+        Maps {
+            pairs,
+            sep,
+            value: None,
+            done: false,
+            first: true,
+        }
+    }
+}
+
+impl<'a, P, S: Separator> Iterator for Maps<'a, P, S>
 where
-    I: Iterator<Item = io::Result<String>>,
+    P: Iterator<Item = Result<(String, String)>>,
 {
     type Item = Result<Vec<(String, String)>>;
     fn next(&mut self) -> Option<Result<Vec<(String, String)>>> {
@@ -105,102 +118,217 @@ where
 
 impl<I> Pairs<I>
 where
-    I: Iterator<Item = io::Result<String>>,
+    I: Iterator<Item = Result<Event>>,
 {
-    pub fn split<'a, 'b: 'a, S: Separator>(&'a mut self, f: S) -> Maps<'a, I, S> {
-        Maps {
-            pairs: self,
-            sep: f,
-            value: None,
-            done: false,
-            first: true,
-        }
+    pub fn split<'a, 'b: 'a, S: Separator>(&'a mut self, f: S) -> Maps<'a, Pairs<I>, S> {
+        Maps::new(self, f)
+    }
+}
+
+/// Endless iterator over protocol events, read with `Source::read_event()`
+pub struct Events<'a, P: 'a + ?Sized>(&'a mut P);
+
+impl<'a, P: 'a + Source + ?Sized> Iterator for Events<'a, P> {
+    type Item = Result<Event>;
+    fn next(&mut self) -> Option<Result<Event>> {
+        Some(self.0.read_event())
     }
 }
 
 // Client inner communication methods {{{
+/// Source of protocol events: a connection, or a response which was already read
 #[doc(hidden)]
-pub trait Proto {
-    type Stream: Read + Write;
+pub trait Source {
+    /// Read next event, blocking until it's decoded
+    fn read_event(&mut self) -> Result<Event>;
+    fn limits(&self) -> Limits;
+}
 
-    fn read_bytes(&mut self, bytes: usize) -> Result<Vec<u8>>;
-    fn read_line(&mut self) -> Result<String>;
-    fn read_pairs(&mut self) -> Pairs<Lines<&mut BufStream<Self::Stream>>>;
+/// Typed readers of responses, available for any event source (including `dyn Source`)
+///
+/// All of them read the response to the end, unless the connection is broken,
+/// so the next command gets its own response.
+#[doc(hidden)]
+pub trait Reader: Source {
+    fn read_pairs(&mut self) -> Pairs<Events<'_, Self>> {
+        Pairs(Events(self))
+    }
 
-    fn run_command<I>(&mut self, command: &str, arguments: I) -> Result<()>
-    where
-        I: ToArguments;
+    /// Read all pairs of a response, returning the first broken line error after the end of it
+    fn collect_pairs(&mut self) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        let mut error = None;
+        for r in self.read_pairs() {
+            match r {
+                Ok(pair) => pairs.push(pair),
+                Err(e) if e.is_recoverable() => {
+                    error.get_or_insert(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(pairs),
+        }
+    }
 
-    fn read_structs<'a, T, S: Separator>(&'a mut self, key: S) -> Result<Vec<T>>
-    where
-        T: 'a + FromIter,
-    {
-        self.read_pairs()
-            .split(key)
-            .map(|v| v.and_then(|v| FromIter::from_iter(v.into_iter().map(Ok))))
-            .collect()
+    fn read_structs<T: FromIter, S: Separator>(&mut self, key: S) -> Result<Vec<T>> {
+        let max = self.limits().records;
+        let mut result = Vec::new();
+        // Keep on reading until the end of response after a recoverable error
+        let mut error = None;
+        for v in self.read_pairs().split(key) {
+            let v = match v {
+                Ok(v) => v,
+                Err(e) if e.is_recoverable() => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if result.len() < max {
+                match FromIter::from_iter(v.into_iter().map(Ok)) {
+                    Ok(v) => result.push(v),
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                }
+            } else {
+                error.get_or_insert(Error::Proto(ProtoError::TooLarge("records")));
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
     }
 
     fn read_list(&mut self, key: &'static str) -> Result<Vec<String>> {
-        self.read_pairs()
-            .filter(|r| r.as_ref().map(|&(ref a, _)| *a == key).unwrap_or(true))
-            .map(|r| r.map(|(_, b)| b))
-            .collect()
+        let max = self.limits().records;
+        let mut result = Vec::new();
+        // Keep on reading until the end of response after a recoverable error
+        let mut error = None;
+        for r in self.read_pairs() {
+            let (a, b) = match r {
+                Ok(pair) => pair,
+                Err(e) if e.is_recoverable() => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if a == key {
+                if result.len() < max {
+                    result.push(b);
+                } else {
+                    error.get_or_insert(Error::Proto(ProtoError::TooLarge("records")));
+                }
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
     }
 
-    fn read_struct<'a, T>(&'a mut self) -> Result<T>
-    where
-        T: 'a + FromIter,
-        Self::Stream: 'a,
-    {
-        FromIter::from_iter(self.read_pairs())
+    fn read_struct<T: FromIter>(&mut self) -> Result<T> {
+        // The whole response is read first, so a bad value doesn't leave the rest of it unread
+        let pairs = self.collect_pairs()?;
+        FromIter::from_iter(pairs.into_iter().map(Ok))
     }
 
     fn drain(&mut self) -> Result<()> {
         loop {
-            let reply = self.read_line()?;
-            match &*reply {
-                "OK" | "list_OK" => break,
-                _ => (),
+            match self.read_event() {
+                Ok(Event::Ok) | Ok(Event::ListOk) | Ok(Event::Ack(_)) => return Ok(()),
+                Ok(_) => (),
+                Err(ref e) if e.is_recoverable() => (),
+                Err(e) => return Err(e),
             }
         }
-        Ok(())
     }
 
     fn expect_ok(&mut self) -> Result<()> {
-        let line = self.read_line()?;
+        match self.read_event()? {
+            Event::Ok | Event::ListOk => Ok(()),
+            Event::Ack(e) => Err(Error::Server(e)),
+            _ => {
+                self.drain()?;
+                Err(Error::Proto(ProtoError::NotOk))
+            }
+        }
+    }
 
-        match line.parse::<Reply>() {
-            Ok(Reply::Ok) => Ok(()),
-            Ok(Reply::Ack(e)) => Err(Error::Server(e)),
-            Ok(_) => Err(Error::Proto(ProtoError::NotOk)),
-            Err(e) => Err(From::from(e)),
+    fn read_field<T: FromStr>(&mut self, field: &'static str) -> Result<T>
+    where
+        ParseError: From<T::Err>,
+    {
+        match self.collect_pairs()?.into_iter().find(|(a, _)| a == field) {
+            Some((_, b)) => Ok(b.parse::<T>().map_err(Into::<ParseError>::into)?),
+            None => Err(Error::Proto(ProtoError::NoField(field))),
         }
     }
+}
 
-    fn read_pair(&mut self) -> Result<(String, String)> {
-        let line = self.read_line()?;
+impl<R: Source + ?Sized> Reader for R {}
 
-        match line.parse::<Reply>() {
-            Ok(Reply::Pair(a, b)) => Ok((a, b)),
-            Ok(Reply::Ok) => Err(Error::Proto(ProtoError::NotPair)),
-            Ok(Reply::Ack(e)) => Err(Error::Server(e)),
-            Err(e) => Err(Error::Parse(e)),
-        }
+#[doc(hidden)]
+pub trait Proto: Source {
+    type Stream: Read + Write;
+
+    /// Write already encoded command(s) and flush them to the server
+    fn write_raw(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Check if the command can be sent to the server
+    fn check_command(&self, _command: &str) -> Result<()> {
+        Ok(())
     }
 
-    fn read_field<T: FromStr>(&mut self, field: &'static str) -> Result<T>
+    fn run_command<I>(&mut self, command: &str, arguments: I) -> Result<()>
     where
-        ParseError: From<T::Err>,
+        I: ToArguments,
     {
-        let (a, b) = self.read_pair()?;
-        self.expect_ok()?;
-        if &*a == field {
-            Ok(b.parse::<T>().map_err(Into::<ParseError>::into)?)
-        } else {
-            Err(Error::Proto(ProtoError::NoField(field)))
+        self.check_command(command)?;
+        let mut buf = Vec::new();
+        encode_command(&mut buf, command, arguments)?;
+        self.write_raw(&buf)
+    }
+}
+
+/// Response which was already read, replayed as events to parse it
+#[doc(hidden)]
+pub struct Replay {
+    events: vec::IntoIter<Result<Event>>,
+    limits: Limits,
+}
+
+impl Replay {
+    /// Replay events of a complete response (ending with `OK`, `list_OK` or `ACK`)
+    pub fn new(events: Vec<Result<Event>>, limits: Limits) -> Replay {
+        Replay {
+            events: events.into_iter(),
+            limits,
         }
     }
+
+    /// Replay a successful response with given pairs
+    pub fn from_pairs(pairs: Vec<(String, String)>, limits: Limits) -> Replay {
+        let events = pairs.into_iter().map(|(a, b)| Ok(Event::Pair(a, b))).chain(Some(Ok(Event::Ok)));
+        Replay::new(events.collect(), limits)
+    }
+}
+
+impl Source for Replay {
+    fn read_event(&mut self) -> Result<Event> {
+        self.events
+            .next()
+            .unwrap_or_else(|| Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "end of response"))))
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
 }
 
 pub trait ToArguments {
@@ -248,6 +376,7 @@ argument_for_display! {usize}
 argument_for_display! {crate::status::ReplayGain}
 argument_for_display! {String}
 argument_for_display! {crate::song::Range}
+argument_for_display! {crate::song::Position}
 argument_for_display! {crate::message::Channel}
 
 macro_rules! argument_for_tuple {
@@ -270,6 +399,18 @@ argument_for_tuple! {t0: T0, t1: T1}
 argument_for_tuple! {t0: T0, t1: T1, t2: T2}
 argument_for_tuple! {t0: T0, t1: T1, t2: T2, t3:T3}
 
+impl<T: ToArguments> ToArguments for Option<T> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+    where
+        F: FnMut(&str) -> StdResult<(), E>,
+    {
+        match self {
+            Some(arg) => arg.to_arguments(f),
+            None => Ok(()),
+        }
+    }
+}
+
 impl<'a, T: ToArguments> ToArguments for &'a [T] {
     fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
     where
@@ -0,0 +1,306 @@
+//! The module defines a client wrapper, which reconnects to MPD when the connection breaks
+//!
+//! ```rust,no_run
+//! # use mpd::{ConnectOptions, ReconnectingClient};
+//! let mut conn = ReconnectingClient::connect("127.0.0.1:6600", ConnectOptions::new()).unwrap();
+//! // Idempotent commands are retried once after reconnection
+//! println!("{:?}", conn.status());
+//! // Other commands fail, but the next call reconnects
+//! println!("{:?}", conn.call(|c| c.next()));
+//! ```
+//!
+//! A connection is considered broken after an IO error or a timeout. Session state
+//! (password, subscriptions, binary limit, tag types and partition) is restored
+//! on reconnection, as long as it's changed with the wrapper methods, not directly on the client.
+
+use crate::client::{Client, DEFAULT_BINARY_LIMIT};
+use crate::connect::ConnectOptions;
+use crate::error::Result;
+use crate::message::Channel;
+use crate::song::{Range, Song};
+use crate::status::Status;
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Reconnection backoff settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// delay before the second attempt, doubled after each failed one
+    pub initial: Duration,
+    /// maximum delay between attempts
+    pub max: Duration,
+    /// number of attempts before giving up (until the next call)
+    pub attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+            attempts: 5,
+        }
+    }
+}
+
+type Connect<S> = Box<dyn FnMut(&ConnectOptions) -> Result<S> + Send>;
+
+/// Client wrapper, which reconnects and restores session state when the connection breaks
+pub struct ReconnectingClient<S: Read + Write = TcpStream> {
+    client: Client<S>,
+    broken: bool,
+    connect: Connect<S>,
+    options: ConnectOptions,
+    subscriptions: Vec<Channel>,
+    backoff: Backoff,
+}
+
+impl<S: Read + Write + fmt::Debug> fmt::Debug for ReconnectingClient<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReconnectingClient")
+            .field("client", &self.client)
+            .field("broken", &self.broken)
+            .field("options", &self.options)
+            .field("subscriptions", &self.subscriptions)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl ReconnectingClient<TcpStream> {
+    /// Connect to some IP address
+    pub fn connect<A>(addr: A, options: ConnectOptions) -> Result<ReconnectingClient<TcpStream>>
+    where
+        A: ToSocketAddrs + Send + 'static,
+    {
+        ReconnectingClient::new(move |options| options.connect_tcp(&addr), options)
+    }
+}
+
+#[cfg(unix)]
+impl ReconnectingClient<UnixStream> {
+    /// Connect to a Unix domain socket (path starting with `@` denotes a Linux abstract socket)
+    pub fn connect_unix<P: Into<PathBuf>>(path: P, options: ConnectOptions) -> Result<ReconnectingClient<UnixStream>> {
+        let path = path.into();
+        ReconnectingClient::new(move |options| options.connect_unix_socket(&path), options)
+    }
+}
+
+impl<S: Read + Write> ReconnectingClient<S> {
+    /// Connect with a given function, which opens a new socket with given options
+    ///
+    /// The first connection is attempted only once, and its error is returned.
+    pub fn new<F>(mut connect: F, options: ConnectOptions) -> Result<ReconnectingClient<S>>
+    where
+        F: FnMut(&ConnectOptions) -> Result<S> + Send + 'static,
+    {
+        let client = Client::with_options(connect(&options)?, &options)?;
+        Ok(ReconnectingClient {
+            client,
+            broken: false,
+            connect: Box::new(connect),
+            options,
+            subscriptions: Vec::new(),
+            backoff: Backoff::default(),
+        })
+    }
+
+    /// Set reconnection backoff settings
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    /// Get the client, reconnecting it if the connection is broken
+    ///
+    /// Session state changed directly on the client is not restored on reconnection.
+    pub fn client(&mut self) -> Result<&mut Client<S>> {
+        if self.broken {
+            self.reconnect()?;
+        }
+        Ok(&mut self.client)
+    }
+
+    /// Run a non-idempotent command (or several ones)
+    ///
+    /// If the connection is broken, the error is returned, and the next call reconnects.
+    pub fn call<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Client<S>) -> Result<T>,
+    {
+        let result = f(self.client()?);
+        if let Err(ref e) = result {
+            if e.is_broken() {
+                self.broken = true;
+            }
+        }
+        result
+    }
+
+    /// Run an idempotent command (or several ones), which is retried once after reconnection
+    pub fn retry<T, F>(&mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(&mut Client<S>) -> Result<T>,
+    {
+        match self.call(&mut f) {
+            Err(ref e) if e.is_broken() => self.call(f),
+            result => result,
+        }
+    }
+
+    /// Get MPD status, retrying after reconnection
+    pub fn status(&mut self) -> Result<Status> {
+        self.retry(Client::status)
+    }
+
+    /// Get current playing song, retrying after reconnection
+    pub fn currentsong(&mut self) -> Result<Option<Song>> {
+        self.retry(Client::currentsong)
+    }
+
+    /// List all songs in a play queue, retrying after reconnection
+    pub fn queue(&mut self) -> Result<Vec<Song>> {
+        self.retry(Client::queue)
+    }
+
+    /// List given song or range of songs in a play queue, retrying after reconnection
+    pub fn playlistinfo<T: Into<Range>>(&mut self, pos: T) -> Result<Vec<Song>> {
+        let pos = pos.into();
+        self.retry(|c| c.playlistinfo(pos))
+    }
+
+    /// Send password, and restore it on reconnection
+    pub fn login(&mut self, password: &str) -> Result<()> {
+        self.call(|c| c.login(password))?;
+        self.options.password(password);
+        Ok(())
+    }
+
+    /// Subscribe to a channel, and restore the subscription on reconnection
+    pub fn subscribe(&mut self, channel: Channel) -> Result<()> {
+        self.call(|c| c.subscribe(channel.clone()))?;
+        if !self.subscriptions.contains(&channel) {
+            self.subscriptions.push(channel);
+        }
+        Ok(())
+    }
+
+    /// Unsubscribe from a channel
+    pub fn unsubscribe(&mut self, channel: Channel) -> Result<()> {
+        self.call(|c| c.unsubscribe(channel.clone()))?;
+        self.subscriptions.retain(|c| *c != channel);
+        Ok(())
+    }
+
+    /// Set maximum binary response chunk size, and restore it on reconnection
+    pub fn binarylimit(&mut self, size: usize) -> Result<()> {
+        self.call(|c| c.binarylimit(size))?;
+        self.options.binary_limit(size);
+        Ok(())
+    }
+
+    /// Restrict tag types in responses to the given ones, and restore it on reconnection
+    pub fn tagtypes(&mut self, tags: &[&str]) -> Result<()> {
+        self.call(|c| {
+            c.tagtypes_clear()?;
+            if tags.is_empty() {
+                return Ok(());
+            }
+            c.tagtypes_enable(tags)
+        })?;
+        self.options.tagtypes(tags);
+        Ok(())
+    }
+
+    /// Switch to a given partition, and restore it on reconnection
+    pub fn partition(&mut self, name: &str) -> Result<()> {
+        self.call(|c| c.partition(name))?;
+        self.options.partition(name);
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let mut delay = self.backoff.initial;
+        let mut attempt = 1;
+        loop {
+            match self.try_reconnect() {
+                Ok(client) => {
+                    self.client = client;
+                    self.broken = false;
+                    return Ok(());
+                }
+                Err(e) if attempt >= self.backoff.attempts => return Err(e),
+                Err(_) => {
+                    thread::sleep(delay);
+                    delay = (delay * 2).min(self.backoff.max);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn try_reconnect(&mut self) -> Result<Client<S>> {
+        let mut options = self.options.clone();
+        // Binary limit may be changed directly on the client (by `albumart()` and alike)
+        if self.client.binary_limit() != DEFAULT_BINARY_LIMIT {
+            options.binary_limit(self.client.binary_limit());
+        }
+
+        let mut client = Client::with_options((self.connect)(&options)?, &options)?;
+        client.copy_settings(&self.client);
+        for channel in &self.subscriptions {
+            client.subscribe(channel.clone())?;
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReconnectingClient;
+    use crate::connect::ConnectOptions;
+    use crate::error::Error;
+    use crate::message::Channel;
+    use crate::test_util::Mock;
+    use std::io;
+
+    #[test]
+    fn reconnect() {
+        let written = Mock::new(b"");
+        let sink = written.reconnect(b"");
+        let mut responses = vec![
+            &b"OK MPD 0.23.0\nOK\nOK\n"[..],
+            &b"OK MPD 0.23.0\nOK\nOK\nfile: a.flac\nPos: 0\nId: 1\nOK\n"[..],
+            &b"OK MPD 0.23.0\nOK\nOK\nOK\n"[..],
+        ]
+        .into_iter();
+        let connect = move |_: &ConnectOptions| match responses.next() {
+            Some(data) => Ok(sink.reconnect(data)),
+            None => Err(Error::Io(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))),
+        };
+
+        let mut options = ConnectOptions::new();
+        options.password("secret");
+        let mut mpd = ReconnectingClient::new(connect, options).unwrap();
+        mpd.subscribe(Channel::new("foo").unwrap()).unwrap();
+
+        // The first connection is closed now
+        assert_eq!(mpd.currentsong().unwrap().unwrap().file, "a.flac");
+        assert!(matches!(mpd.call(|c| c.next()), Err(Error::Io(_))));
+        assert!(mpd.call(|c| c.ping()).is_ok());
+
+        assert_eq!(
+            written.written(),
+            "password \"secret\"\nsubscribe \"foo\"\ncurrentsong\n\
+             password \"secret\"\nsubscribe \"foo\"\ncurrentsong\nnext\n\
+             password \"secret\"\nsubscribe \"foo\"\nping\n"
+        );
+    }
+}
@@ -4,6 +4,7 @@
 //! all possible server replies.
 
 use crate::error::{ParseError, ServerError};
+use crate::proto::Maps;
 use std::str::FromStr;
 
 /// All possible MPD server replies
@@ -33,3 +34,57 @@ impl FromStr for Reply {
         }
     }
 }
+
+/// Complete generic response to a command, as returned by `Client::raw_command()`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Response {
+    /// data pairs (`field: value`) in the order they were received
+    pub pairs: Vec<(String, String)>,
+    /// binary payload, if any
+    pub binary: Option<Vec<u8>>,
+}
+
+impl Response {
+    /// Get the first value of a field
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(a, _)| a == key).map(|(_, b)| &**b)
+    }
+
+    /// Get all values of a field
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(a, _)| a == key).map(|(_, b)| &**b)
+    }
+
+    /// Split pairs into records, each one starting with one of the given fields
+    ///
+    /// E.g. `split(&["file", "directory"])` splits `lsinfo` response into separate entries.
+    pub fn split(&self, keys: &[&str]) -> Vec<Vec<(String, String)>> {
+        let mut pairs = self.pairs.iter().cloned().map(Ok);
+        Maps::new(&mut pairs, keys).filter_map(Result::ok).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn response_split() {
+        let pair = |a: &str, b: &str| (a.to_owned(), b.to_owned());
+        let response = Response {
+            pairs: vec![
+                pair("directory", "a"),
+                pair("Last-Modified", "x"),
+                pair("file", "a/b.flac"),
+                pair("Title", "b"),
+                pair("file", "a/c.flac"),
+            ],
+            binary: None,
+        };
+        let records = response.split(&["directory", "file"]);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], vec![pair("file", "a/b.flac"), pair("Title", "b")]);
+        assert_eq!(response.get("file"), Some("a/b.flac"));
+        assert_eq!(response.get_all("file").collect::<Vec<_>>(), vec!["a/b.flac", "a/c.flac"]);
+    }
+}
@@ -0,0 +1,764 @@
+//! The module defines typed requests, shared by blocking and asynchronous clients
+//!
+//! A [`Request`](struct.Request.html) is a command (or a command list) encoded with
+//! [`encode_command`](../codec/fn.encode_command.html), together with a parser of its response.
+//! The parser reads events from any [`Source`](../proto/trait.Source.html): `Client` lets it read
+//! straight from the connection, `AsyncClient` reads the whole response first and replays it.
+//! So every command is defined once here, and both clients are thin drivers of these definitions.
+
+use crate::codec::{encode_command, Event};
+use crate::error::{Error, ProtoError, Result};
+#[cfg(feature = "tokio")]
+use crate::idle::{self, Subsystem};
+use crate::list::ListGroup;
+use crate::lsinfo::LsInfoResponse;
+use crate::message::{Channel, Message};
+use crate::mount::{Mount, Neighbor};
+use crate::output::Output;
+use crate::picture::PictureInfo;
+use crate::playlist::Playlist;
+use crate::plugin::Plugin;
+use crate::proto::{Maps, Reader, Source, ToArguments};
+use crate::reply::Response;
+use crate::search::{Groups, Query, Sort, Term, Window};
+use crate::song::{Position, Range, Song};
+use crate::stats::{Count, Stats};
+use crate::status::{ReplayGain, Status};
+use crate::sticker::Sticker;
+use crate::version::{required_version, Version};
+
+use std::collections::{HashMap, HashSet};
+
+// Request {{{
+pub(crate) type Parser<T> = Box<dyn FnOnce(&mut dyn Source) -> Result<T> + Send>;
+
+/// Encoded command(s) and a parser of the response
+pub(crate) struct Request<T> {
+    buf: Vec<u8>,
+    // the newest protocol version required by the commands
+    required: Option<(String, Version)>,
+    parse: Parser<T>,
+}
+
+impl Request<()> {
+    /// Request for a command, which responds with a plain `OK`
+    pub fn new<I: ToArguments>(command: &str, arguments: I) -> Result<Request<()>> {
+        let request = Request {
+            buf: Vec::new(),
+            required: None,
+            parse: Box::new(|r| r.expect_ok()),
+        };
+        request.and(command, arguments)
+    }
+}
+
+impl<T> Request<T> {
+    /// Append another command, which is sent along with the previous ones
+    pub fn and<I: ToArguments>(mut self, command: &str, arguments: I) -> Result<Request<T>> {
+        encode_command(&mut self.buf, command, arguments)?;
+        Ok(match required_version(command) {
+            Some(version) => self.require(command, version),
+            None => self,
+        })
+    }
+
+    /// Require a protocol version for the request (e.g. for some of its arguments)
+    pub fn require(mut self, what: &str, version: Version) -> Request<T> {
+        match self.required {
+            Some((_, required)) if required >= version => (),
+            _ => self.required = Some((what.to_owned(), version)),
+        }
+        self
+    }
+
+    /// Require a protocol version for an optional argument, if it's given
+    pub fn require_if(self, given: bool, what: &str, version: Version) -> Request<T> {
+        if given {
+            self.require(what, version)
+        } else {
+            self
+        }
+    }
+
+    /// Replace the parser of the response
+    pub fn reply<U, F>(self, parse: F) -> Request<U>
+    where
+        F: FnOnce(&mut dyn Source) -> Result<U> + Send + 'static,
+    {
+        Request {
+            buf: self.buf,
+            required: self.required,
+            parse: Box::new(parse),
+        }
+    }
+
+    /// Check if the request is supported by the server protocol version
+    pub fn check(&self, actual: Version) -> Result<()> {
+        match self.required {
+            Some((ref command, required)) if actual < required => Err(Error::Unsupported {
+                command: command.clone(),
+                required,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Encoded command(s) to send
+    pub fn encoded(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Parse the response read from a source
+    pub fn parse(self, source: &mut dyn Source) -> Result<T> {
+        (self.parse)(source)
+    }
+
+    /// Split into encoded command(s) and the parser
+    pub fn into_parts(self) -> (Vec<u8>, Parser<T>) {
+        (self.buf, self.parse)
+    }
+}
+// }}}
+
+// Playback options & status {{{
+/// Status with replay gain mode, which needs a command list
+pub fn full_status() -> Result<Request<Status>> {
+    Ok(Request::new("command_list_begin", ())?
+        .and("status", ())?
+        .and("replay_gain_status", ())?
+        .and("command_list_end", ())?
+        .reply(|r| r.read_struct()))
+}
+
+/// Status without replay gain mode, which can be used in a command list
+pub fn status() -> Result<Request<Status>> {
+    Ok(Request::new("status", ())?.reply(|r| r.read_struct()))
+}
+
+pub fn stats() -> Result<Request<Stats>> {
+    Ok(Request::new("stats", ())?.reply(|r| r.read_struct()))
+}
+
+pub fn clearerror() -> Result<Request<()>> {
+    Request::new("clearerror", ())
+}
+
+pub fn volume(volume: i8) -> Result<Request<()>> {
+    Request::new("setvol", volume)
+}
+
+pub fn repeat(value: bool) -> Result<Request<()>> {
+    Request::new("repeat", value as u8)
+}
+
+pub fn random(value: bool) -> Result<Request<()>> {
+    Request::new("random", value as u8)
+}
+
+pub fn single(value: bool) -> Result<Request<()>> {
+    Request::new("single", value as u8)
+}
+
+pub fn consume(value: bool) -> Result<Request<()>> {
+    Request::new("consume", value as u8)
+}
+
+pub fn crossfade(value: u32) -> Result<Request<()>> {
+    Request::new("crossfade", value)
+}
+
+pub fn mixrampdb(value: f32) -> Result<Request<()>> {
+    Request::new("mixrampdb", value)
+}
+
+pub fn mixrampdelay(value: u32) -> Result<Request<()>> {
+    Request::new("mixrampdelay", value)
+}
+
+pub fn replaygain(gain: ReplayGain) -> Result<Request<()>> {
+    Request::new("replay_gain_mode", gain)
+}
+// }}}
+
+// Playback control {{{
+pub fn play() -> Result<Request<()>> {
+    Request::new("play", ())
+}
+
+pub fn play_from_position(place: u32) -> Result<Request<()>> {
+    Request::new("play", place)
+}
+
+pub fn play_from_id(place: u32) -> Result<Request<()>> {
+    Request::new("playid", place)
+}
+
+pub fn next() -> Result<Request<()>> {
+    Request::new("next", ())
+}
+
+pub fn prev() -> Result<Request<()>> {
+    Request::new("previous", ())
+}
+
+pub fn stop() -> Result<Request<()>> {
+    Request::new("stop", ())
+}
+
+pub fn toggle_pause() -> Result<Request<()>> {
+    Request::new("pause", ())
+}
+
+pub fn pause(value: bool) -> Result<Request<()>> {
+    Request::new("pause", value as u8)
+}
+
+pub fn seek(place: u32, pos: u32) -> Result<Request<()>> {
+    Request::new("seek", (place, pos))
+}
+
+pub fn seek_id(place: u32, pos: u32) -> Result<Request<()>> {
+    Request::new("seekid", (place, pos))
+}
+
+pub fn rewind(pos: u32) -> Result<Request<()>> {
+    Request::new("seekcur", pos)
+}
+// }}}
+
+// Queue control {{{
+pub fn playlistinfo(pos: Range) -> Result<Request<Vec<Song>>> {
+    Ok(Request::new("playlistinfo", pos)?.reply(|r| r.read_structs("file")))
+}
+
+pub fn playlistid(pos: u32) -> Result<Request<Song>> {
+    Ok(Request::new("playlistid", pos)?.reply(|r| r.read_struct()))
+}
+
+pub fn queue() -> Result<Request<Vec<Song>>> {
+    Ok(Request::new("playlistinfo", ())?.reply(|r| r.read_structs("file")))
+}
+
+pub fn currentsong() -> Result<Request<Option<Song>>> {
+    Ok(Request::new("currentsong", ())?.reply(|r| r.read_struct::<Song>().map(|s| if s.place.is_none() { None } else { Some(s) })))
+}
+
+pub fn clear() -> Result<Request<()>> {
+    Request::new("clear", ())
+}
+
+pub fn changes(version: u32) -> Result<Request<Vec<Song>>> {
+    Ok(Request::new("plchanges", version)?.reply(|r| r.read_structs("file")))
+}
+
+pub fn add(path: &str) -> Result<Request<()>> {
+    Request::new("add", path)
+}
+
+pub fn push(path: &str) -> Result<Request<u32>> {
+    Ok(Request::new("addid", path)?.reply(|r| r.read_field("Id")))
+}
+
+pub fn insert(path: &str, pos: usize) -> Result<Request<u32>> {
+    Ok(Request::new("addid", (path, pos))?.reply(|r| r.read_field("Id")))
+}
+
+pub fn delete(pos: Range) -> Result<Request<()>> {
+    Request::new("delete", pos)
+}
+
+pub fn deleteid(id: u32) -> Result<Request<()>> {
+    Request::new("deleteid", id)
+}
+
+pub fn move_range(from: Range, to: usize) -> Result<Request<()>> {
+    Request::new("move", (from, to))
+}
+
+pub fn moveid(from: u32, to: usize) -> Result<Request<()>> {
+    Request::new("moveid", (from, to))
+}
+
+pub fn swap(one: u32, two: u32) -> Result<Request<()>> {
+    Request::new("swap", (one, two))
+}
+
+pub fn swapid(one: u32, two: u32) -> Result<Request<()>> {
+    Request::new("swapid", (one, two))
+}
+
+pub fn shuffle(range: Range) -> Result<Request<()>> {
+    Request::new("shuffle", range)
+}
+
+pub fn prio(pos: Range, prio: u8) -> Result<Request<()>> {
+    Request::new("prio", (prio, pos))
+}
+
+pub fn prioid(id: u32, prio: u8) -> Result<Request<()>> {
+    Request::new("prioid", (prio, id))
+}
+
+pub fn range(song: u32, range: Range) -> Result<Request<()>> {
+    Request::new("rangeid", (song, range))
+}
+
+pub fn tag(song: u32, tag: &str, value: &str) -> Result<Request<()>> {
+    Request::new("addtagid", (song, tag, value))
+}
+
+pub fn untag(song: u32, tag: &str) -> Result<Request<()>> {
+    Request::new("cleartagid", (song, tag))
+}
+// }}}
+
+// Connection settings {{{
+pub fn ping() -> Result<Request<()>> {
+    Request::new("ping", ())
+}
+
+pub fn close() -> Result<Request<()>> {
+    Request::new("close", ())
+}
+
+pub fn kill() -> Result<Request<()>> {
+    Request::new("kill", ())
+}
+
+pub fn login(password: &str) -> Result<Request<()>> {
+    Request::new("password", password)
+}
+
+pub fn binarylimit(size: usize) -> Result<Request<()>> {
+    Request::new("binarylimit", size)
+}
+// }}}
+
+// Playlist methods {{{
+pub fn playlists() -> Result<Request<Vec<Playlist>>> {
+    Ok(Request::new("listplaylists", ())?.reply(|r| r.read_structs("playlist")))
+}
+
+pub fn playlist(name: &str) -> Result<Request<Vec<Song>>> {
+    Ok(Request::new("listplaylistinfo", name)?.reply(|r| r.read_structs("file")))
+}
+
+pub fn load(name: &str, range: Range) -> Result<Request<()>> {
+    Request::new("load", (name, range))
+}
+
+pub fn save(name: &str) -> Result<Request<()>> {
+    Request::new("save", name)
+}
+
+pub fn pl_rename(name: &str, newname: &str) -> Result<Request<()>> {
+    Request::new("rename", (name, newname))
+}
+
+pub fn pl_clear(name: &str) -> Result<Request<()>> {
+    Request::new("playlistclear", name)
+}
+
+pub fn pl_remove(name: &str) -> Result<Request<()>> {
+    Request::new("rm", name)
+}
+
+pub fn pl_push(name: &str, path: &str) -> Result<Request<()>> {
+    Request::new("playlistadd", (name, path))
+}
+
+pub fn pl_delete(name: &str, pos: u32) -> Result<Request<()>> {
+    Request::new("playlistdelete", (name, pos))
+}
+
+pub fn pl_shift(name: &str, from: u32, to: u32) -> Result<Request<()>> {
+    Request::new("playlistmove", (name, from, to))
+}
+// }}}
+
+// Database methods {{{
+pub fn rescan() -> Result<Request<u32>> {
+    Ok(Request::new("rescan", ())?.reply(|r| r.read_field("updating_db")))
+}
+
+pub fn update() -> Result<Request<u32>> {
+    Ok(Request::new("update", ())?.reply(|r| r.read_field("updating_db")))
+}
+
+pub fn listfiles(path: &str) -> Result<Request<Vec<(String, String)>>> {
+    Ok(Request::new("listfiles", path)?.reply(|r| r.collect_pairs()))
+}
+
+/// `find` or `search`
+pub fn find(command: &str, query: &Query, sort: Sort, window: Window) -> Result<Request<Vec<Song>>> {
+    query.check()?;
+    Ok(Request::new(command, (query, sort, window))?
+        .require_if(sort.is_some(), &format!("{} sort", command), Version(0, 21, 0))
+        .require_if(window.is_some(), &format!("{} window", command), Version(0, 20, 0))
+        .reply(|r| r.read_structs("file")))
+}
+
+pub fn count(query: &Query, group: Option<Term>) -> Result<Request<Vec<Count>>> {
+    query.check()?;
+    let request = match group {
+        Some(ref term) => Request::new("count", (query, "group", term))?.require("count group", Version(0, 21, 0)),
+        None => Request::new("count", query)?,
+    };
+    let group = group.map(|term| term.to_string());
+    Ok(request.reply(move |r| Count::from_pairs(group.as_deref(), r.collect_pairs()?.into_iter().map(Ok))))
+}
+
+pub fn list(term: &Term, query: &Query, window: Window) -> Result<Request<Vec<String>>> {
+    query.check()?;
+    Ok(Request::new("list", (term, query, window))?
+        .require_if(window.is_some(), "list window", Version(0, 24, 0))
+        .reply(|r| r.collect_pairs().map(|pairs| pairs.into_iter().map(|p| p.1).collect())))
+}
+
+pub fn list_grouped(term: &Term, query: &Query, groups: &[Term], window: Window) -> Result<Request<Vec<ListGroup>>> {
+    query.check()?;
+    let term = term.to_string();
+    Ok(Request::new("list", (&*term, query, Groups(groups), window))?
+        .require_if(!groups.is_empty(), "list group", Version(0, 21, 0))
+        .require_if(window.is_some(), "list window", Version(0, 24, 0))
+        .reply(move |r| ListGroup::from_pairs(&term, r.collect_pairs()?.into_iter().map(Ok))))
+}
+
+/// `findadd` or `searchadd`
+pub fn findadd(command: &str, query: &Query, sort: Sort, window: Window, pos: Option<Position>) -> Result<Request<()>> {
+    query.check()?;
+    Ok(Request::new(command, ((query, sort, window), pos.map(|p| ("position", p))))?
+        .require_if(sort.is_some(), &format!("{} sort", command), Version(0, 21, 0))
+        .require_if(window.is_some(), &format!("{} window", command), Version(0, 21, 0))
+        .require_if(pos.is_some(), &format!("{} position", command), Version(0, 23, 0)))
+}
+
+pub fn searchaddpl(name: &str, query: &Query, sort: Sort, window: Window, pos: Option<u32>) -> Result<Request<()>> {
+    query.check()?;
+    Ok(Request::new("searchaddpl", ((name, query, sort, window), pos.map(|p| ("position", p))))?
+        .require_if(sort.is_some(), "searchaddpl sort", Version(0, 21, 0))
+        .require_if(window.is_some(), "searchaddpl window", Version(0, 21, 0))
+        .require_if(pos.is_some(), "searchaddpl position", Version(0, 23, 0)))
+}
+
+/// `lsinfo`, `listall` or `listallinfo`, read completely
+pub fn lsinfo(command: &str, path: &str) -> Result<Request<Vec<LsInfoResponse>>> {
+    Ok(Request::new(command, path)?.reply(|r| r.read_structs(&["directory", "file", "playlist"][..])))
+}
+
+#[cfg(feature = "tokio")]
+pub fn readcomments(path: &str) -> Result<Request<Vec<(String, String)>>> {
+    Ok(Request::new("readcomments", path)?.reply(|r| r.collect_pairs()))
+}
+// }}}
+
+// Binary transfers {{{
+/// Chunk of a binary object (like a picture)
+pub(crate) struct Chunk {
+    /// total size of the object
+    pub size: usize,
+    pub mime: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Get a chunk of a binary object at a given offset with `albumart` or `readpicture`
+///
+/// An empty response (no object) gives `None`.
+pub fn binary_chunk(command: &str, path: &str, offset: usize) -> Result<Request<Option<Chunk>>> {
+    Ok(Request::new(command, (path, offset))?.reply(move |r| read_chunk(r, offset)))
+}
+
+fn read_chunk(r: &mut dyn Source, offset: usize) -> Result<Option<Chunk>> {
+    let mut size = None;
+    let mut mime = None;
+    let bytes = loop {
+        match r.read_event() {
+            Ok(Event::Ok) | Ok(Event::ListOk) => return Ok(None),
+            Ok(Event::Ack(e)) => return Err(Error::Server(e)),
+            Ok(Event::Pair(a, b)) => match &*a {
+                "size" => size = Some(b),
+                "type" => mime = Some(b),
+                "binary" => break b,
+                _ => (),
+            },
+            Ok(_) => return r.drain().and(Err(Error::Proto(ProtoError::BadBinary))),
+            Err(e) if e.is_recoverable() => return r.drain().and(Err(e)),
+            Err(e) => return Err(e),
+        }
+    };
+
+    let limit = r.limits().binary_size;
+    let header = match (size.map(|s| s.parse::<usize>()), bytes.parse::<usize>()) {
+        (None, _) => Err(Error::Proto(ProtoError::NoField("size"))),
+        (Some(Err(e)), _) | (_, Err(e)) => Err(Error::Parse(e.into())),
+        (Some(Ok(size)), Ok(_)) if size > limit => Err(Error::Proto(ProtoError::TooLarge("binary"))),
+        (Some(Ok(size)), Ok(bytes)) => Ok((size, bytes)),
+    };
+    let (size, bytes) = match header {
+        Ok(header) => header,
+        Err(e) => return r.drain().and(Err(e)),
+    };
+
+    match r.read_event() {
+        Ok(Event::Binary(data)) if data.len() == bytes && offset + bytes <= size => {
+            r.expect_ok()?;
+            Ok(Some(Chunk { size, mime, data }))
+        }
+        Ok(Event::Ok) | Ok(Event::ListOk) | Ok(Event::Ack(_)) => Err(Error::Proto(ProtoError::BadBinary)),
+        Ok(_) => r.drain().and(Err(Error::Proto(ProtoError::BadBinary))),
+        Err(e) if e.is_recoverable() => r.drain().and(Err(e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Progress of a binary transfer, which takes one request per chunk
+pub(crate) struct Transfer {
+    received: usize,
+    mime: Option<String>,
+    raised: bool,
+}
+
+impl Transfer {
+    pub fn new(offset: usize) -> Transfer {
+        Transfer {
+            received: offset,
+            mime: None,
+            raised: false,
+        }
+    }
+
+    /// Number of bytes received so far (including the initial offset)
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Account a received chunk, returns object info if the transfer is complete
+    pub fn feed(&mut self, chunk: &mut Chunk) -> Option<PictureInfo> {
+        self.received += chunk.data.len();
+        if chunk.mime.is_some() {
+            self.mime = chunk.mime.take();
+        }
+        if chunk.data.is_empty() || chunk.size <= self.received {
+            return Some(PictureInfo {
+                size: chunk.size,
+                mime: self.mime.take(),
+            });
+        }
+        None
+    }
+
+    /// Binary limit to set for the rest of the transfer, if it should be raised
+    ///
+    /// It's raised only once, and not above the maximum (see `Client::set_max_binary_limit`).
+    pub fn raise_limit(&mut self, size: usize, current: usize, max: Option<usize>) -> Option<usize> {
+        let remaining = size - self.received;
+        match max {
+            Some(max) if !self.raised && remaining > current && max > current => {
+                self.raised = true;
+                Some(remaining.min(max))
+            }
+            _ => None,
+        }
+    }
+}
+// }}}
+
+// Output methods {{{
+pub fn outputs() -> Result<Request<Vec<Output>>> {
+    Ok(Request::new("outputs", ())?.reply(|r| r.read_structs("outputid")))
+}
+
+pub fn output(id: u32, state: bool) -> Result<Request<()>> {
+    Request::new(if state { "enableoutput" } else { "disableoutput" }, id)
+}
+
+pub fn out_toggle(id: u32) -> Result<Request<()>> {
+    Request::new("toggleoutput", id)
+}
+// }}}
+
+// Reflection methods {{{
+pub fn music_directory() -> Result<Request<String>> {
+    Ok(Request::new("config", ())?.reply(|r| r.read_field("music_directory")))
+}
+
+/// `commands` or `notcommands`
+pub fn commands(command: &str) -> Result<Request<Vec<String>>> {
+    Ok(Request::new(command, ())?.reply(|r| r.read_list("command")))
+}
+
+/// Set of allowed commands, given lists of all and forbidden commands
+pub fn allowed_commands(all: Vec<String>, not: Vec<String>) -> HashSet<String> {
+    let mut commands: HashSet<String> = all.into_iter().collect();
+    for command in not {
+        commands.remove(&command);
+    }
+    commands
+}
+
+/// Check if a command (like `sticker get`) is in a set of allowed commands
+pub fn is_allowed(commands: &HashSet<String>, command: &str) -> bool {
+    commands.contains(command.split(' ').next().unwrap_or(command))
+}
+
+pub fn urlhandlers() -> Result<Request<Vec<String>>> {
+    Ok(Request::new("urlhandlers", ())?.reply(|r| r.read_list("handler")))
+}
+
+pub fn tagtypes() -> Result<Request<Vec<String>>> {
+    Ok(Request::new("tagtypes", ())?.reply(|r| r.read_list("tagtype")))
+}
+
+/// `tagtypes clear`, `tagtypes all`, `tagtypes enable` or `tagtypes disable`
+pub fn tagtypes_set(command: &str, tags: &[&str]) -> Result<Request<()>> {
+    Request::new(command, tags)
+}
+
+pub fn partition(name: &str) -> Result<Request<()>> {
+    Request::new("partition", name)
+}
+
+pub fn decoders() -> Result<Request<Vec<Plugin>>> {
+    Ok(Request::new("decoders", ())?.reply(|r| r.read_struct()))
+}
+// }}}
+
+// Messaging {{{
+pub fn channels() -> Result<Request<Vec<Channel>>> {
+    Ok(Request::new("channels", ())?.reply(|r| {
+        r.read_list("channel")
+            .map(|v| v.into_iter().map(|b| unsafe { Channel::new_unchecked(b) }).collect())
+    }))
+}
+
+pub fn readmessages() -> Result<Request<Vec<Message>>> {
+    Ok(Request::new("readmessages", ())?.reply(|r| r.read_structs("channel")))
+}
+
+pub fn sendmessage(channel: Channel, message: &str) -> Result<Request<()>> {
+    channel.check()?;
+    Request::new("sendmessage", (channel, message))
+}
+
+/// `subscribe` or `unsubscribe`
+pub fn subscribe(command: &str, channel: Channel) -> Result<Request<()>> {
+    channel.check()?;
+    Request::new(command, channel)
+}
+// }}}
+
+// Mount methods {{{
+pub fn mounts() -> Result<Request<Vec<Mount>>> {
+    Ok(Request::new("listmounts", ())?.reply(|r| r.read_structs("mount")))
+}
+
+pub fn neighbors() -> Result<Request<Vec<Neighbor>>> {
+    Ok(Request::new("listneighbors", ())?.reply(|r| r.read_structs("neighbor")))
+}
+
+pub fn mount(path: &str, uri: &str) -> Result<Request<()>> {
+    Request::new("mount", (path, uri))
+}
+
+pub fn unmount(path: &str) -> Result<Request<()>> {
+    Request::new("unmount", path)
+}
+// }}}
+
+// Sticker methods {{{
+pub fn sticker(typ: &str, uri: &str, name: &str) -> Result<Request<String>> {
+    // TODO: This should parse to a `Sticker` type.
+    Ok(Request::new("sticker get", (typ, uri, name))?.reply(|r| r.read_field::<Sticker>("sticker").map(|s| s.value)))
+}
+
+pub fn set_sticker(typ: &str, uri: &str, name: &str, value: &str) -> Result<Request<()>> {
+    Request::new("sticker set", (typ, uri, name, value))
+}
+
+pub fn delete_sticker(typ: &str, uri: &str, name: &str) -> Result<Request<()>> {
+    Request::new("sticker delete", (typ, uri, name))
+}
+
+pub fn clear_stickers(typ: &str, uri: &str) -> Result<Request<()>> {
+    Request::new("sticker delete", (typ, uri))
+}
+
+pub fn stickers(typ: &str, uri: &str) -> Result<Request<Vec<String>>> {
+    Ok(Request::new("sticker list", (typ, uri))?.reply(|r| {
+        r.read_list("sticker")
+            .and_then(|v| v.into_iter().map(|b| split_sticker(&b).map(|(_, value)| value)).collect())
+    }))
+}
+
+pub fn stickers_map(typ: &str, uri: &str) -> Result<Request<HashMap<String, String>>> {
+    Ok(Request::new("sticker list", (typ, uri))?.reply(|r| {
+        r.read_list("sticker")
+            .and_then(|v| v.into_iter().map(|b| split_sticker(&b)).collect())
+    }))
+}
+
+pub fn find_sticker(typ: &str, uri: &str, name: &str) -> Result<Request<Vec<(String, String)>>> {
+    Ok(Request::new("sticker find", (typ, uri, name))?.reply(|r| {
+        let mut pairs = r.collect_pairs()?.into_iter().map(Ok);
+        Maps::new(&mut pairs, "file")
+            .map(|rmap| {
+                rmap.and_then(|map| {
+                    let field = |key| map.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+                    let file = field("file").ok_or(Error::Proto(ProtoError::NoField("file")))?;
+                    let sticker = field("sticker").ok_or(Error::Proto(ProtoError::NoField("sticker")))?;
+                    Ok((file.to_owned(), split_sticker(sticker)?.1))
+                })
+            })
+            .collect()
+    }))
+}
+
+pub fn find_sticker_eq(typ: &str, uri: &str, name: &str, value: &str) -> Result<Request<Vec<String>>> {
+    Ok(Request::new("sticker find", (typ, uri, name, value))?.reply(|r| r.read_list("file")))
+}
+
+/// Split `name=value` sticker pair
+fn split_sticker(sticker: &str) -> Result<(String, String)> {
+    match sticker.split_once('=') {
+        Some((name, value)) => Ok((name.to_owned(), value.to_owned())),
+        None => Err(Error::Proto(ProtoError::BadSticker)),
+    }
+}
+// }}}
+
+// Other commands {{{
+/// Wait for changes in subsystems, unknown subsystem names are skipped
+#[cfg(feature = "tokio")]
+pub fn idle(subsystems: &[Subsystem]) -> Result<Request<Vec<Subsystem>>> {
+    Ok(Request::new("idle", subsystems)?.reply(|r| r.read_list("changed").map(idle::parse_changed)))
+}
+
+/// Arbitrary command, see `Client::raw_command`
+pub fn raw(command: &str, arguments: &[&str]) -> Result<Request<Response>> {
+    Ok(Request::new(command, arguments)?.reply(|r| {
+        let mut response = Response::default();
+        // Keep on reading until the end of response
+        let mut error = None;
+        loop {
+            match r.read_event() {
+                Ok(Event::Ok) | Ok(Event::ListOk) => break,
+                Ok(Event::Ack(e)) => return Err(Error::Server(e)),
+                Ok(Event::Pair(a, b)) => response.pairs.push((a, b)),
+                Ok(Event::Binary(data)) => response.binary = Some(data),
+                Ok(Event::Greeting(_)) => {
+                    error.get_or_insert(Error::Proto(ProtoError::NotPair));
+                }
+                Err(e) if e.is_recoverable() => {
+                    error.get_or_insert(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(response),
+        }
+    }))
+}
+// }}}
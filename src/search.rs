@@ -1,10 +1,13 @@
 #![allow(missing_docs)]
 // TODO: unfinished functionality
 
-use crate::proto::ToArguments;
+use crate::error::{Error, Result};
+use crate::proto::{Quoted, ToArguments};
 use std::fmt;
+use std::ops;
 use std::result::Result as StdResult;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Term<'a> {
     Any,
     File,
@@ -24,8 +27,15 @@ impl<'a> Filter<'a> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Window(Option<(u32, u32)>);
 
+impl Window {
+    pub(crate) fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
 impl From<(u32, u32)> for Window {
     fn from(window: (u32, u32)) -> Window {
         Window(Some(window))
@@ -38,6 +48,64 @@ impl From<Option<(u32, u32)>> for Window {
     }
 }
 
+/// Field to sort search results by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey<'a> {
+    /// tag name, like `artist` or `date`
+    Tag(&'a str),
+    /// file modification time (`Last-Modified`)
+    LastModified,
+    /// time the file was added to the database (`Added`)
+    Added,
+    /// queue priority (`prio`)
+    Priority,
+}
+
+impl<'a> fmt::Display for SortKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            SortKey::Tag(tag) => tag,
+            SortKey::LastModified => "Last-Modified",
+            SortKey::Added => "Added",
+            SortKey::Priority => "prio",
+        })
+    }
+}
+
+/// Sort order of search results
+///
+/// Use `None` for the server's default order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort<'a>(Option<(SortKey<'a>, bool)>);
+
+impl<'a> Sort<'a> {
+    /// Sort by given key in ascending order
+    pub fn ascending(key: SortKey<'a>) -> Sort<'a> {
+        Sort(Some((key, false)))
+    }
+
+    /// Sort by given key in descending order (`-key`)
+    pub fn descending(key: SortKey<'a>) -> Sort<'a> {
+        Sort(Some((key, true)))
+    }
+
+    pub(crate) fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl<'a> From<SortKey<'a>> for Sort<'a> {
+    fn from(key: SortKey<'a>) -> Sort<'a> {
+        Sort::ascending(key)
+    }
+}
+
+impl<'a> From<Option<SortKey<'a>>> for Sort<'a> {
+    fn from(key: Option<SortKey<'a>>) -> Sort<'a> {
+        Sort(key.map(|key| (key, false)))
+    }
+}
+
 #[derive(Default)]
 pub struct FilterQuery<'a> {
     filters: Vec<Filter<'a>>,
@@ -45,7 +113,200 @@ pub struct FilterQuery<'a> {
 
 pub enum Query<'a> {
     Filters(FilterQuery<'a>),
-    Expression(String),
+    Expression(Expression<'a>),
+}
+
+impl<'a> Query<'a> {
+    /// Check the query can be rendered as a valid filter before sending it to server
+    pub(crate) fn check(&self) -> Result<()> {
+        match *self {
+            Query::Filters(_) => Ok(()),
+            Query::Expression(ref expr) => expr.check(),
+        }
+    }
+}
+
+impl<'a> From<FilterQuery<'a>> for Query<'a> {
+    fn from(filters: FilterQuery<'a>) -> Query<'a> {
+        Query::Filters(filters)
+    }
+}
+
+impl<'a> From<Expression<'a>> for Query<'a> {
+    fn from(expression: Expression<'a>) -> Query<'a> {
+        Query::Expression(expression)
+    }
+}
+
+/// Comparison operator of a filter expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    /// `==`, exact match
+    Equals,
+    /// `!=`, exact mismatch
+    NotEquals,
+    /// `contains`, substring match
+    Contains,
+    /// `starts_with`, prefix match
+    StartsWith,
+    /// `=~`, regular expression match
+    Matches,
+    /// `!~`, regular expression mismatch
+    NotMatches,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Operation::Equals => "==",
+            Operation::NotEquals => "!=",
+            Operation::Contains => "contains",
+            Operation::StartsWith => "starts_with",
+            Operation::Matches => "=~",
+            Operation::NotMatches => "!~",
+        })
+    }
+}
+
+/// Filter expression, as described in [MPD protocol docs][filters]
+///
+/// Values are quoted and escaped when the expression is rendered,
+/// so they can be given verbatim.
+///
+/// ```rust
+/// use mpd::search::{Expression, Query};
+/// use mpd::Term;
+///
+/// let query: Query = Expression::eq(Term::Tag("artist"), "Miles Davis")
+///     .and(!Expression::contains(Term::Tag("album"), "Live"))
+///     .into();
+/// ```
+///
+/// [filters]: https://mpd.readthedocs.io/en/latest/protocol.html#filters
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression<'a> {
+    /// `(TAG OP 'VALUE')`, compare tag (or `file`, or `any`) with a value
+    Compare(Term<'a>, Operation, &'a str),
+    /// `(base 'VALUE')`, songs in given directory
+    Base(&'a str),
+    /// `(modified-since 'VALUE')`, songs modified since given ISO 8601 timestamp or UNIX time
+    ModifiedSince(&'a str),
+    /// `(added-since 'VALUE')`, songs added since given ISO 8601 timestamp or UNIX time
+    AddedSince(&'a str),
+    /// `(AudioFormat == 'SAMPLERATE:BITS:CHANNELS')`, songs with exact audio format
+    AudioFormat(&'a str),
+    /// `(AudioFormat =~ 'SAMPLERATE:BITS:CHANNELS')`, songs matching audio format mask (`*` wildcards allowed)
+    AudioFormatMask(&'a str),
+    /// `(prio >= N)`, queued songs with priority at least given value
+    Priority(u8),
+    /// `(!EXPRESSION)`, negation
+    Not(Box<Expression<'a>>),
+    /// `(EXPRESSION1 AND EXPRESSION2 ...)`, conjunction
+    And(Vec<Expression<'a>>),
+}
+
+impl<'a> Expression<'a> {
+    /// `(TAG == 'VALUE')`
+    pub fn eq(term: Term<'a>, value: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::Equals, value)
+    }
+
+    /// `(TAG != 'VALUE')`
+    pub fn ne(term: Term<'a>, value: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::NotEquals, value)
+    }
+
+    /// `(TAG contains 'VALUE')`
+    pub fn contains(term: Term<'a>, value: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::Contains, value)
+    }
+
+    /// `(TAG starts_with 'VALUE')`
+    pub fn starts_with(term: Term<'a>, value: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::StartsWith, value)
+    }
+
+    /// `(TAG =~ 'VALUE')`
+    pub fn matches(term: Term<'a>, regex: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::Matches, regex)
+    }
+
+    /// `(TAG !~ 'VALUE')`
+    pub fn not_matches(term: Term<'a>, regex: &'a str) -> Expression<'a> {
+        Expression::Compare(term, Operation::NotMatches, regex)
+    }
+
+    /// Check the expression has a valid MPD syntax
+    ///
+    /// `base` and `modified-since` can be only compared with `==` or `!=`,
+    /// and `AND` needs at least one operand.
+    pub(crate) fn check(&self) -> Result<()> {
+        match *self {
+            Expression::Compare(Term::Base, op, _) | Expression::Compare(Term::LastMod, op, _)
+                if op != Operation::Equals && op != Operation::NotEquals =>
+            {
+                Err(Error::BadArgument(self.to_string()))
+            }
+            Expression::Not(ref expr) => expr.check(),
+            Expression::And(ref exprs) if exprs.is_empty() => Err(Error::BadArgument(self.to_string())),
+            Expression::And(ref exprs) => exprs.iter().try_for_each(Expression::check),
+            _ => Ok(()),
+        }
+    }
+
+    /// Combine two expressions with `AND`
+    ///
+    /// Nested conjunctions are flattened into a single `AND` list.
+    pub fn and(self, other: Expression<'a>) -> Expression<'a> {
+        let mut exprs = match self {
+            Expression::And(exprs) => exprs,
+            expr => vec![expr],
+        };
+        match other {
+            Expression::And(others) => exprs.extend(others),
+            expr => exprs.push(expr),
+        }
+        Expression::And(exprs)
+    }
+}
+
+impl<'a> ops::Not for Expression<'a> {
+    type Output = Expression<'a>;
+    fn not(self) -> Expression<'a> {
+        Expression::Not(Box::new(self))
+    }
+}
+
+impl<'a> fmt::Display for Expression<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            // `base` and `modified-since` have their own syntax without an operator
+            Expression::Compare(Term::Base, Operation::Equals, value) => Expression::Base(value).fmt(f),
+            Expression::Compare(Term::LastMod, Operation::Equals, value) => Expression::ModifiedSince(value).fmt(f),
+            Expression::Compare(Term::Base, Operation::NotEquals, value) => write!(f, "(!{})", Expression::Base(value)),
+            Expression::Compare(Term::LastMod, Operation::NotEquals, value) => {
+                write!(f, "(!{})", Expression::ModifiedSince(value))
+            }
+            Expression::Compare(ref term, op, value) => write!(f, "({} {} {})", term, op, Quoted(value)),
+            Expression::Base(value) => write!(f, "(base {})", Quoted(value)),
+            Expression::ModifiedSince(value) => write!(f, "(modified-since {})", Quoted(value)),
+            Expression::AddedSince(value) => write!(f, "(added-since {})", Quoted(value)),
+            Expression::AudioFormat(value) => write!(f, "(AudioFormat == {})", Quoted(value)),
+            Expression::AudioFormatMask(value) => write!(f, "(AudioFormat =~ {})", Quoted(value)),
+            Expression::Priority(prio) => write!(f, "(prio >= {})", prio),
+            Expression::Not(ref expr) => write!(f, "(!{})", expr),
+            Expression::And(ref exprs) => {
+                f.write_str("(")?;
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" AND ")?;
+                    }
+                    expr.fmt(f)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
 }
 
 impl<'a> FilterQuery<'a> {
@@ -80,6 +341,21 @@ impl<'a> ToArguments for &'a Term<'a> {
     }
 }
 
+pub(crate) struct Groups<'a>(pub &'a [Term<'a>]);
+
+impl<'a> ToArguments for Groups<'a> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+    where
+        F: FnMut(&str) -> StdResult<(), E>,
+    {
+        for term in self.0 {
+            f("group")?;
+            term.to_arguments(f)?;
+        }
+        Ok(())
+    }
+}
+
 impl<'a> ToArguments for &'a Filter<'a> {
     fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
     where
@@ -114,6 +390,15 @@ impl<'a> ToArguments for &'a Query<'a> {
     }
 }
 
+impl<'a> ToArguments for &'a Expression<'a> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+    where
+        F: FnMut(&str) -> StdResult<(), E>,
+    {
+        f(&self.to_string())
+    }
+}
+
 impl ToArguments for Window {
     fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
     where
@@ -127,6 +412,23 @@ impl ToArguments for Window {
     }
 }
 
+impl<'a> ToArguments for Sort<'a> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+    where
+        F: FnMut(&str) -> StdResult<(), E>,
+    {
+        if let Some((key, descending)) = self.0 {
+            f("sort")?;
+            if descending {
+                f(&format!("-{}", key))?;
+            } else {
+                f(&key.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -147,6 +449,14 @@ mod test {
         assert_eq!(output, vec!["window", "0:2"]);
     }
 
+    #[test]
+    fn sort_format() {
+        assert_eq!(collect(Sort::from(None)), Vec::<String>::new());
+        assert_eq!(collect(Sort::from(SortKey::Tag("date"))), vec!["sort", "date"]);
+        assert_eq!(collect(Sort::descending(SortKey::LastModified)), vec!["sort", "-Last-Modified"]);
+        assert_eq!(collect((Sort::descending(SortKey::Priority), Window::from((0, 5)))), vec!["sort", "-prio", "window", "0:5"]);
+    }
+
     #[test]
     fn find_query_format() {
         let mut query = FilterQuery::new();
@@ -165,4 +475,35 @@ mod test {
         let output = collect(&query);
         assert_eq!(output, vec!["albumartist", "Mac DeMarco", "album", "Salad Days"]);
     }
+
+    #[test]
+    fn expression_format() {
+        let expr = Expression::eq(Term::Tag("albumartist"), "Mac DeMarco")
+            .and(Expression::contains(Term::Tag("album"), "Salad"))
+            .and(!Expression::Base("Live"))
+            .and(Expression::Priority(10));
+        let output = collect(&Query::Expression(expr));
+        assert_eq!(output, vec![r#"((albumartist == "Mac DeMarco") AND (album contains "Salad") AND (!(base "Live")) AND (prio >= 10))"#]);
+    }
+
+    #[test]
+    fn expression_special_terms() {
+        assert_eq!(Expression::eq(Term::Base, "Jazz").to_string(), r#"(base "Jazz")"#);
+        assert_eq!(Expression::ne(Term::Base, "Jazz").to_string(), r#"(!(base "Jazz"))"#);
+        assert_eq!(Expression::eq(Term::LastMod, "2020-01-01").to_string(), r#"(modified-since "2020-01-01")"#);
+        assert!(Query::from(Expression::eq(Term::Base, "Jazz")).check().is_ok());
+        assert!(Query::from(FilterQuery::new()).check().is_ok());
+
+        assert!(matches!(Expression::contains(Term::Base, "Jazz").check(), Err(Error::BadArgument(_))));
+        assert!(matches!((!Expression::matches(Term::LastMod, "2020")).check(), Err(Error::BadArgument(_))));
+        assert!(matches!(Expression::And(vec![]).check(), Err(Error::BadArgument(_))));
+        let nested = Expression::eq(Term::Any, "foo").and(!Expression::And(vec![]));
+        assert!(matches!(Query::from(nested).check(), Err(Error::BadArgument(_))));
+    }
+
+    #[test]
+    fn expression_escape() {
+        let expr = Expression::eq(Term::Tag("artist"), r#"foo'bar"\"#);
+        assert_eq!(collect(&expr), vec![r#"(artist == "foo'bar\"\\")"#]);
+    }
 }
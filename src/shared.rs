@@ -0,0 +1,373 @@
+//! The module defines a thread-safe client, which listens for events between commands
+//!
+//! [`SharedClient`](struct.SharedClient.html) keeps a single connection parked in idle mode
+//! by a background thread. When a command is called from any thread, idle mode is interrupted
+//! with `noidle`, the command is run, and the connection goes back to idle mode.
+//! Changed subsystems (either reported by the server, or collected by `noidle`) are passed
+//! to registered listeners on the background thread, along with the client to run commands.
+//!
+//! ```rust,no_run
+//! # use mpd::{SharedClient, Subsystem};
+//! let conn = SharedClient::connect("127.0.0.1:6600").unwrap();
+//! conn.add_listener(|conn, subsystem| {
+//!     if subsystem == Subsystem::Player {
+//!         println!("{:?}", conn.call(|c| c.currentsong()));
+//!     }
+//! });
+//!
+//! let other = conn.clone();
+//! std::thread::spawn(move || other.call(|c| c.next()));
+//! println!("{:?}", conn.call(|c| c.status()));
+//! ```
+//!
+//! Listeners may call commands with the client passed to them, but must not add or remove listeners.
+//! A listener shouldn't own a client clone, as the connection is only closed
+//! when all clones are dropped, and listeners are dropped with the connection.
+//! Events are not delivered anymore after a connection error.
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::idle::{self, Subsystem};
+use crate::proto::{Proto, Reader};
+use crate::stream::Stream;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+
+/// Socket, which can be cloned to write `noidle` while another thread reads from it
+pub trait TryClone: Sized {
+    /// Create an independently owned handle to the same socket
+    fn try_clone(&self) -> io::Result<Self>;
+}
+
+impl TryClone for TcpStream {
+    fn try_clone(&self) -> io::Result<TcpStream> {
+        TcpStream::try_clone(self)
+    }
+}
+
+#[cfg(unix)]
+impl TryClone for UnixStream {
+    fn try_clone(&self) -> io::Result<UnixStream> {
+        UnixStream::try_clone(self)
+    }
+}
+
+impl TryClone for Stream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+}
+
+type Listener<S> = Box<dyn FnMut(&SharedClient<S>, Subsystem) + Send>;
+
+/// Handle of a registered listener, used to remove it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+#[derive(Default)]
+struct State {
+    // number of commands waiting for the connection or running
+    pending: usize,
+    idling: bool,
+    stopped: bool,
+}
+
+struct Inner<S: Read + Write> {
+    client: Mutex<Client<S>>,
+    writer: Mutex<S>,
+    state: Mutex<State>,
+    resumed: Condvar,
+    listeners: Mutex<Vec<(ListenerId, Listener<S>)>>,
+    next_id: AtomicU64,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<S: Read + Write> Inner<S> {
+    /// Take the connection out of idle mode for a command
+    fn interrupt(&self) -> Result<()> {
+        let mut state = lock(&self.state);
+        if state.idling {
+            // MPD ignores `noidle` if it has just left idle mode by itself
+            let mut writer = lock(&self.writer);
+            writer.write_all(b"noidle\n").and_then(|_| writer.flush())?;
+            state.idling = false;
+        }
+        state.pending += 1;
+        Ok(())
+    }
+
+    /// Let the background thread put the connection back into idle mode
+    fn resume(&self) {
+        let mut state = lock(&self.state);
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.resumed.notify_all();
+        }
+    }
+
+    fn run(&self, worker: Weak<Worker<S>>) {
+        loop {
+            let mut state = lock(&self.state);
+            while state.pending > 0 && !state.stopped {
+                state = self.resumed.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.stopped {
+                return;
+            }
+
+            // `idle` is sent with the state locked, so a command can't miss it and skip `noidle`
+            let mut client = lock(&self.client);
+            if client.run_command("idle", ()).is_err() {
+                state.stopped = true;
+                return;
+            }
+            client.idling = true;
+            state.idling = true;
+            drop(state);
+
+            let result = client.read_list("changed");
+            client.idling = false;
+            drop(client);
+            lock(&self.state).idling = false;
+
+            let events = match result {
+                Ok(events) => events,
+                Err(_) => {
+                    lock(&self.state).stopped = true;
+                    return;
+                }
+            };
+            // The worker is only held while listeners run, so it can still be dropped
+            let client = match worker.upgrade() {
+                Some(worker) => SharedClient {
+                    inner: worker.inner.clone(),
+                    worker,
+                },
+                None => return,
+            };
+            let mut listeners = lock(&self.listeners);
+            for event in idle::parse_changed(events) {
+                for (_, listener) in listeners.iter_mut() {
+                    listener(&client, event);
+                }
+            }
+        }
+    }
+}
+
+/// Background thread handle, the thread is stopped when the last client clone is dropped
+struct Worker<S: Read + Write> {
+    inner: Arc<Inner<S>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: Read + Write> Drop for Worker<S> {
+    fn drop(&mut self) {
+        {
+            let mut state = lock(&self.inner.state);
+            state.stopped = true;
+            if state.idling {
+                let mut writer = lock(&self.inner.writer);
+                let _ = writer.write_all(b"noidle\n").and_then(|_| writer.flush());
+            }
+            self.inner.resumed.notify_all();
+        }
+        if let Some(thread) = self.thread.take() {
+            // The last clone may be dropped by a listener on the background thread itself
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Thread-safe cloneable client, which keeps the connection in idle mode between commands
+pub struct SharedClient<S: Read + Write = TcpStream> {
+    inner: Arc<Inner<S>>,
+    worker: Arc<Worker<S>>,
+}
+
+impl<S: Read + Write> Clone for SharedClient<S> {
+    fn clone(&self) -> SharedClient<S> {
+        SharedClient {
+            inner: self.inner.clone(),
+            worker: self.worker.clone(),
+        }
+    }
+}
+
+impl<S: Read + Write> fmt::Debug for SharedClient<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedClient")
+            .field("listeners", &lock(&self.inner.listeners).len())
+            .finish()
+    }
+}
+
+impl SharedClient<TcpStream> {
+    /// Connect to MPD server over TCP
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<SharedClient<TcpStream>> {
+        Client::connect(addr).and_then(SharedClient::new)
+    }
+}
+
+#[cfg(unix)]
+impl SharedClient<UnixStream> {
+    /// Connect to a Unix domain socket (path starting with `@` denotes a Linux abstract socket)
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<SharedClient<UnixStream>> {
+        Client::connect_unix(path).and_then(SharedClient::new)
+    }
+}
+
+impl<S: Read + Write + TryClone + Send + 'static> SharedClient<S> {
+    /// Share an already connected client, and start listening for events
+    pub fn new(client: Client<S>) -> Result<SharedClient<S>> {
+        let inner = Arc::new(Inner {
+            writer: Mutex::new(client.socket().try_clone()?),
+            client: Mutex::new(client),
+            state: Mutex::new(State::default()),
+            resumed: Condvar::new(),
+            listeners: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        });
+        let (sender, receiver) = channel();
+        let runner = inner.clone();
+        let thread = thread::spawn(move || {
+            if let Ok(worker) = receiver.recv() {
+                runner.run(worker);
+            }
+        });
+        let worker = Arc::new(Worker {
+            inner: inner.clone(),
+            thread: Some(thread),
+        });
+        // The thread only gets a weak handle, so it doesn't keep the worker alive
+        let _ = sender.send(Arc::downgrade(&worker));
+        Ok(SharedClient { inner, worker })
+    }
+}
+
+impl<S: Read + Write> SharedClient<S> {
+    /// Run commands on the client
+    ///
+    /// Idle mode is interrupted before the call, and entered again after it
+    /// (unless another thread is waiting to run its commands).
+    pub fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Client<S>) -> Result<T>,
+    {
+        self.inner.interrupt()?;
+        let result = f(&mut lock(&self.inner.client));
+        self.inner.resume();
+        result
+    }
+
+    /// Register a function to be called with the client and every changed subsystem
+    pub fn add_listener<F>(&self, listener: F) -> ListenerId
+    where
+        F: FnMut(&SharedClient<S>, Subsystem) + Send + 'static,
+    {
+        let id = ListenerId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        lock(&self.inner.listeners).push((id, Box::new(listener)));
+        id
+    }
+
+    /// Remove a registered listener, returns `false` if there was no such listener
+    pub fn remove_listener(&self, id: ListenerId) -> bool {
+        let mut listeners = lock(&self.inner.listeners);
+        let count = listeners.len();
+        listeners.retain(|(i, _)| *i != id);
+        listeners.len() != count
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::SharedClient;
+    use crate::client::Client;
+    use crate::idle::Subsystem;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn shared() {
+        fn thread_safe<T: Send + Sync>() {}
+        thread_safe::<SharedClient>();
+        thread_safe::<SharedClient<UnixStream>>();
+
+        let (socket, mut server) = UnixStream::pair().unwrap();
+        let mut lines = BufReader::new(server.try_clone().unwrap()).lines().map(Result::unwrap);
+        server.write_all(b"OK MPD 0.23.0\n").unwrap();
+        let mpd = SharedClient::new(Client::new(socket).unwrap()).unwrap();
+
+        let (events, received) = channel();
+        mpd.add_listener(move |_, subsystem| events.send(subsystem).unwrap());
+
+        // A server side event
+        assert_eq!(lines.next().unwrap(), "idle");
+        server.write_all(b"changed: mixer\nOK\n").unwrap();
+        assert_eq!(received.recv().unwrap(), Subsystem::Mixer);
+
+        // A command from another thread interrupts idle mode, events are still delivered
+        assert_eq!(lines.next().unwrap(), "idle");
+        let other = mpd.clone();
+        let command = thread::spawn(move || other.call(|c| c.ping()));
+        assert_eq!(lines.next().unwrap(), "noidle");
+        server.write_all(b"changed: player\nOK\n").unwrap();
+        assert_eq!(lines.next().unwrap(), "ping");
+        server.write_all(b"OK\n").unwrap();
+        command.join().unwrap().unwrap();
+        assert_eq!(received.recv().unwrap(), Subsystem::Player);
+
+        // Back in idle mode, which is interrupted when the last clone is dropped
+        assert_eq!(lines.next().unwrap(), "idle");
+        let stop = thread::spawn(move || drop(mpd));
+        assert_eq!(lines.next().unwrap(), "noidle");
+        server.write_all(b"OK\n").unwrap();
+        stop.join().unwrap();
+    }
+    #[test]
+    fn listener_commands() {
+        let (socket, mut server) = UnixStream::pair().unwrap();
+        let mut lines = BufReader::new(server.try_clone().unwrap()).lines().map(Result::unwrap);
+        server.write_all(b"OK MPD 0.23.0\n").unwrap();
+        let mpd = SharedClient::new(Client::new(socket).unwrap()).unwrap();
+
+        let (results, received) = channel();
+        mpd.add_listener(move |mpd, _| results.send(mpd.call(|c| c.ping()).is_ok()).unwrap());
+
+        // A listener runs commands with the client passed to it
+        assert_eq!(lines.next().unwrap(), "idle");
+        server.write_all(b"changed: mixer\nOK\n").unwrap();
+        assert_eq!(lines.next().unwrap(), "ping");
+        server.write_all(b"OK\n").unwrap();
+        assert!(received.recv().unwrap());
+
+        // The thread exits and closes the connection when the last clone is dropped
+        assert_eq!(lines.next().unwrap(), "idle");
+        let stop = thread::spawn(move || drop(mpd));
+        assert_eq!(lines.next().unwrap(), "noidle");
+        server.write_all(b"OK\n").unwrap();
+        stop.join().unwrap();
+        assert!(lines.next().is_none());
+    }
+}
@@ -19,6 +19,33 @@ pub struct QueuePlace {
     pub prio: u8,
 }
 
+/// Queue position to insert songs at
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Position {
+    /// absolute zero-based position in the queue
+    Absolute(u32),
+    /// position relative to the current song, `AfterCurrent(0)` being right after it (`+N`)
+    AfterCurrent(u32),
+    /// position relative to the current song, `BeforeCurrent(0)` being right before it (`-N`)
+    BeforeCurrent(u32),
+}
+
+impl From<u32> for Position {
+    fn from(pos: u32) -> Position {
+        Position::Absolute(pos)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Position::Absolute(pos) => pos.fmt(f),
+            Position::AfterCurrent(pos) => write!(f, "+{}", pos),
+            Position::BeforeCurrent(pos) => write!(f, "-{}", pos),
+        }
+    }
+}
+
 /// Song range
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Range(pub Option<u32>, pub Option<u32>);
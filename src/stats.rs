@@ -1,4 +1,4 @@
-//! The module describes DB and playback statistics
+//! The module describes DB and playback statistics, including per-query `count` results
 
 use crate::convert::FromIter;
 use crate::error::Error;
@@ -60,3 +60,90 @@ impl FromIter for Stats {
         Ok(result)
     }
 }
+
+/// Number of songs and their total playtime, as reported by `count` command
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Count {
+    /// value of the group tag, if results were grouped
+    pub group: Option<String>,
+    /// number of songs
+    pub songs: u32,
+    /// total playtime of the songs, seconds resolution
+    pub playtime: Duration,
+}
+
+impl Count {
+    /// Build counts from `count` response pairs, one per value of `group` tag (if given)
+    ///
+    /// Only the group tag starts a new entry, any other unknown fields are ignored.
+    pub(crate) fn from_pairs<I>(group: Option<&str>, iter: I) -> Result<Vec<Count>, Error>
+    where
+        I: Iterator<Item = Result<(String, String), Error>>,
+    {
+        let mut result = Vec::new();
+        let mut count: Option<Count> = None;
+
+        for res in iter {
+            let (key, value) = res?;
+            match &*key {
+                "songs" => count.get_or_insert_with(Count::default).songs = value.parse()?,
+                "playtime" => count.get_or_insert_with(Count::default).playtime = Duration::from_secs(value.parse()?),
+                _ if group.is_some_and(|g| key.eq_ignore_ascii_case(g)) => {
+                    if let Some(c) = count.take() {
+                        result.push(c);
+                    }
+                    count = Some(Count {
+                        group: Some(value),
+                        ..Count::default()
+                    });
+                }
+                _ => (),
+            }
+        }
+        if let Some(c) = count {
+            result.push(c);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(input: &[(&str, &str)]) -> impl Iterator<Item = Result<(String, String), Error>> {
+        input
+            .iter()
+            .map(|&(a, b)| Ok((a.to_owned(), b.to_owned())))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn grouped_counts() {
+        let input = [
+            ("Artist", "A"),
+            ("songs", "2"),
+            ("playtime", "300"),
+            ("new_field", "1"),
+            ("Artist", "B"),
+            ("songs", "1"),
+            ("playtime", "100"),
+        ];
+        let counts = Count::from_pairs(Some("artist"), pairs(&input)).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!((counts[0].group.as_deref(), counts[0].songs), (Some("A"), 2));
+        assert_eq!((counts[1].group.as_deref(), counts[1].playtime), (Some("B"), Duration::from_secs(100)));
+
+        let counts = Count::from_pairs(None, pairs(&[("songs", "3"), ("playtime", "400"), ("new_field", "1")])).unwrap();
+        assert_eq!(
+            counts,
+            vec![Count {
+                group: None,
+                songs: 3,
+                playtime: Duration::from_secs(400)
+            }]
+        );
+    }
+}
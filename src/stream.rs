@@ -0,0 +1,216 @@
+//! The module defines a stream type, which connects to MPD either over TCP or a Unix domain socket
+//!
+//! It's used by [`Client::from_env()`](../client/struct.Client.html#method.from_env),
+//! where transport is only known at runtime.
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default MPD port
+pub const DEFAULT_PORT: u16 = 6600;
+
+/// Connection to MPD over TCP or a Unix domain socket
+#[derive(Debug)]
+pub enum Stream {
+    /// TCP connection
+    Tcp(TcpStream),
+    /// Unix domain socket connection
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Stream {
+        Stream::Tcp(stream)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Stream {
+        Stream::Unix(stream)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.flush(),
+        }
+    }
+}
+
+/// Socket with a configurable read timeout
+pub trait ReadTimeout {
+    /// Get the current read timeout, `None` means reads block forever
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+    /// Set the read timeout, a timed out read fails with `WouldBlock` or `TimedOut` error
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl ReadTimeout for UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        UnixStream::read_timeout(self)
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl ReadTimeout for Stream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match *self {
+            Stream::Tcp(ref s) => s.read_timeout(),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.read_timeout(),
+        }
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref s) => s.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.set_read_timeout(timeout),
+        }
+    }
+}
+
+/// Address of MPD server
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Address {
+    Tcp(String, u16),
+    Unix(PathBuf),
+}
+
+/// Connect to a Unix domain socket, path starting with `@` denotes a Linux abstract socket
+#[cfg(unix)]
+pub(crate) fn connect_unix(path: &Path) -> io::Result<UnixStream> {
+    use std::os::unix::ffi::OsStrExt;
+
+    match path.as_os_str().as_bytes().strip_prefix(b"@") {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Some(name) => {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets are not supported")),
+        None => UnixStream::connect(path),
+    }
+}
+
+/// Addresses to try and password to send, as configured by `MPD_HOST` and `MPD_PORT`
+pub(crate) fn env_addresses() -> Result<(Vec<Address>, Option<String>), std::num::ParseIntError> {
+    parse_env(env::var("MPD_HOST").ok(), env::var("MPD_PORT").ok(), env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from))
+}
+
+/// Resolve addresses following libmpdclient rules
+fn parse_env(
+    host: Option<String>,
+    port: Option<String>,
+    runtime_dir: Option<PathBuf>,
+) -> Result<(Vec<Address>, Option<String>), std::num::ParseIntError> {
+    let port = match port {
+        Some(port) if !port.is_empty() => port.parse()?,
+        _ => DEFAULT_PORT,
+    };
+
+    let host = match host {
+        Some(host) if !host.is_empty() => host,
+        _ => {
+            let mut addresses = Vec::new();
+            if let Some(dir) = runtime_dir {
+                addresses.push(Address::Unix(dir.join("mpd").join("socket")));
+            }
+            addresses.push(Address::Tcp("localhost".into(), port));
+            return Ok((addresses, None));
+        }
+    };
+
+    // Anything starting with `/` is a socket path, which may contain `@` itself
+    if host.starts_with('/') {
+        return Ok((vec![Address::Unix(host.into())], None));
+    }
+
+    // `password@host`, but a host starting with `@` is an abstract socket name
+    let (password, host) = match host.find('@') {
+        Some(i) if i > 0 => (Some(host[..i].to_owned()), host[i + 1..].to_owned()),
+        _ => (None, host),
+    };
+
+    let address = if host.starts_with('/') || host.starts_with('@') {
+        Address::Unix(host.into())
+    } else if host.is_empty() {
+        Address::Tcp("localhost".into(), port)
+    } else {
+        Address::Tcp(host, port)
+    };
+    Ok((vec![address], password))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_env, Address};
+    use std::path::PathBuf;
+
+    fn parse(host: Option<&str>, port: Option<&str>) -> (Vec<Address>, Option<String>) {
+        parse_env(host.map(Into::into), port.map(Into::into), Some(PathBuf::from("/run/user/1000"))).unwrap()
+    }
+
+    #[test]
+    fn env_rules() {
+        assert_eq!(
+            parse(None, None).0,
+            vec![
+                Address::Unix("/run/user/1000/mpd/socket".into()),
+                Address::Tcp("localhost".into(), 6600)
+            ]
+        );
+        assert_eq!(parse(Some("example.com"), Some("6601")), (vec![Address::Tcp("example.com".into(), 6601)], None));
+        assert_eq!(parse(Some("secret@example.com"), None), (vec![Address::Tcp("example.com".into(), 6600)], Some("secret".into())));
+        assert_eq!(parse(Some("/run/mpd/socket"), Some("6601")), (vec![Address::Unix("/run/mpd/socket".into())], None));
+        assert_eq!(parse(Some("/run/mpd@x/socket"), None), (vec![Address::Unix("/run/mpd@x/socket".into())], None));
+        assert_eq!(parse(Some("@mpd"), None), (vec![Address::Unix("@mpd".into())], None));
+        assert_eq!(parse(Some("secret@@mpd"), None), (vec![Address::Unix("@mpd".into())], Some("secret".into())));
+        assert!(parse_env(None, Some("x".into()), None).is_err());
+    }
+}
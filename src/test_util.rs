@@ -0,0 +1,62 @@
+//! Helpers shared by unit tests
+
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// In-memory stream, which replays canned server responses and records commands written to it
+#[derive(Debug)]
+pub struct Mock {
+    input: Cursor<Vec<u8>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    timeout: bool,
+}
+
+impl Mock {
+    /// Create a stream with given server responses
+    pub fn new(input: &[u8]) -> Mock {
+        Mock {
+            input: Cursor::new(input.to_vec()),
+            output: Arc::new(Mutex::new(Vec::new())),
+            timeout: false,
+        }
+    }
+
+    /// Create another stream with given responses, which records commands along with this one
+    pub fn reconnect(&self, input: &[u8]) -> Mock {
+        Mock {
+            input: Cursor::new(input.to_vec()),
+            output: self.output.clone(),
+            timeout: false,
+        }
+    }
+
+    /// Fail reads with `WouldBlock` after all responses, like a socket with a read timeout
+    pub fn time_out(mut self) -> Mock {
+        self.timeout = true;
+        self
+    }
+
+    /// All commands written so far
+    pub fn written(&self) -> String {
+        String::from_utf8(self.output.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Read for Mock {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.input.read(buf)? {
+            0 if self.timeout => Err(io::Error::new(io::ErrorKind::WouldBlock, "timed out")),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for Mock {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 //! This module defines MPD version type and parsing code
 
-use crate::error::ParseError;
+use crate::error::{Error, ParseError};
+use std::fmt;
 use std::str::FromStr;
 
 // Version {{{
@@ -19,4 +20,46 @@ impl FromStr for Version {
         }
     }
 }
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Minimum protocol version required by a command, if it's newer than the oldest supported one
+pub(crate) fn required_version(command: &str) -> Option<Version> {
+    let version = match command {
+        "tagtypes clear" | "tagtypes all" | "tagtypes enable" | "tagtypes disable" => Version(0, 21, 0),
+        _ => match command.split(' ').next().unwrap_or(command) {
+            "idle" | "noidle" => Version(0, 14, 0),
+            "sticker" => Version(0, 15, 0),
+            "replay_gain_mode" | "replay_gain_status" => Version(0, 16, 0),
+            "seekcur" | "prio" | "prioid" | "searchaddpl" | "toggleoutput" => Version(0, 17, 0),
+            "subscribe" | "unsubscribe" | "channels" | "readmessages" | "sendmessage" => Version(0, 17, 0),
+            "config" | "listfiles" | "readcomments" | "addtagid" | "cleartagid" | "rangeid" => Version(0, 19, 0),
+            "mount" | "unmount" | "listmounts" | "listneighbors" => Version(0, 19, 0),
+            "albumart" | "getfingerprint" | "outputset" => Version(0, 21, 0),
+            "partition" | "listpartitions" | "newpartition" => Version(0, 21, 0),
+            "readpicture" | "delpartition" | "moveoutput" => Version(0, 22, 0),
+            "binarylimit" => Version(0, 22, 4),
+            "getvol" => Version(0, 23, 0),
+            "protocol" | "stickertypes" | "stickernames" | "searchplaylist" | "playlistlength" => Version(0, 24, 0),
+            _ => return None,
+        },
+    };
+    Some(version)
+}
+
+/// Check if the command is supported by the server protocol version
+pub(crate) fn check_version(command: &str, actual: Version) -> Result<(), Error> {
+    match required_version(command) {
+        Some(required) if actual < required => Err(Error::Unsupported {
+            command: command.to_owned(),
+            required,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
 // }}}
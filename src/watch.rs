@@ -0,0 +1,338 @@
+//! The module defines a watcher, which turns idle notifications into typed events
+//!
+//! `idle` only reports which subsystems have changed, so a client has to fetch
+//! status and compare it with what it has seen before to find out what actually happened.
+//! [`Watcher`](struct.Watcher.html) keeps the last known status, current song and outputs,
+//! and does this comparison, emitting an [`Event`](enum.Event.html) per change.
+//!
+//! ```rust,no_run
+//! # use mpd::watch::{Event, Watcher};
+//! # use mpd::Client;
+//! let mut watcher = Watcher::new(Client::connect("127.0.0.1:6600").unwrap()).unwrap();
+//! for event in &mut watcher {
+//!     match event.unwrap() {
+//!         Event::SongChanged { to: Some(song), .. } => println!("now playing {}", song.file),
+//!         Event::VolumeChanged(volume) => println!("volume {}", volume),
+//!         other => println!("{:?}", other),
+//!     }
+//! }
+//! ```
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::idle::{Idle, Subsystem};
+use crate::output::Output;
+use crate::song::Song;
+use crate::status::{ReplayGain, State, Status};
+use crate::stream::ReadTimeout;
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Change observed by a watcher, values are the new ones unless stated otherwise
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// another song became current (or playback reached the end of the queue)
+    SongChanged {
+        /// previous song
+        from: Option<Song>,
+        /// new current song
+        to: Option<Song>,
+    },
+    /// playback was started, paused or stopped
+    StateChanged(State),
+    /// volume was changed (-1 if volume became unavailable)
+    VolumeChanged(i8),
+    /// some of playback options were changed
+    OptionsChanged {
+        /// repeat mode
+        repeat: bool,
+        /// random mode
+        random: bool,
+        /// single mode
+        single: bool,
+        /// consume mode
+        consume: bool,
+        /// crossfade timeout
+        crossfade: Option<Duration>,
+        /// replay gain mode
+        replaygain: Option<ReplayGain>,
+    },
+    /// an output with a given id was enabled or disabled
+    OutputToggled(u32, bool),
+    /// the song database was modified after update
+    DatabaseUpdated,
+    /// the queue was modified
+    QueueChanged {
+        /// new queue version
+        version: u32,
+    },
+    /// a subsystem without a typed event (like stored playlists or stickers) was changed
+    Other(Subsystem),
+}
+
+/// Watcher of player state changes
+///
+/// It owns a client, which is put into idle mode while waiting for events.
+/// As an iterator it ends after an IO error or a timeout, as the connection is unusable then.
+#[derive(Debug)]
+pub struct Watcher<S: Read + Write = TcpStream> {
+    client: Client<S>,
+    status: Status,
+    song: Option<Song>,
+    outputs: Vec<Output>,
+    events: VecDeque<Event>,
+    done: bool,
+}
+
+impl<S: Read + Write> Watcher<S> {
+    /// Start watching, fetching the initial state
+    pub fn new(mut client: Client<S>) -> Result<Watcher<S>> {
+        Ok(Watcher {
+            status: client.status()?,
+            song: client.currentsong()?,
+            outputs: client.outputs()?,
+            client,
+            events: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Last known status
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Last known current song
+    pub fn song(&self) -> Option<&Song> {
+        self.song.as_ref()
+    }
+
+    /// Last known outputs
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Get the client to run commands, their effects are reported as events later
+    pub fn client(&mut self) -> &mut Client<S> {
+        &mut self.client
+    }
+
+    /// Stop watching and return the client
+    pub fn into_inner(self) -> Client<S> {
+        self.client
+    }
+
+    /// Wait for the next event
+    pub fn next_event(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let changed = self.client.wait(&[])?;
+            self.update(&changed)?;
+        }
+    }
+
+    /// Wait for the next event at most for a given time, returns `None` on timeout
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<Event>>
+    where
+        S: ReadTimeout,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let changed = self.client.wait_timeout(&[], remaining)?;
+            self.update(&changed)?;
+        }
+    }
+
+    /// Refresh the state affected by changed subsystems, and queue events for differences
+    fn update(&mut self, changed: &[Subsystem]) -> Result<()> {
+        use self::Subsystem::*;
+
+        let has = |subsystem| changed.contains(&subsystem);
+        if has(Player) || has(Mixer) || has(Options) || has(Queue) {
+            let status = self.client.status()?;
+            if has(Player) || has(Queue) {
+                let song = self.client.currentsong()?;
+                let key = |song: &Option<Song>| song.as_ref().map(|s| (s.file.clone(), s.place.map(|p| p.id)));
+                if key(&song) != key(&self.song) {
+                    let from = std::mem::replace(&mut self.song, song.clone());
+                    self.events.push_back(Event::SongChanged { from, to: song });
+                } else {
+                    self.song = song;
+                }
+            }
+            self.diff_status(status);
+        }
+
+        if has(Output) {
+            let outputs = self.client.outputs()?;
+            for output in &outputs {
+                if let Some(old) = self.outputs.iter().find(|o| o.id == output.id) {
+                    if old.enabled != output.enabled {
+                        self.events.push_back(Event::OutputToggled(output.id, output.enabled));
+                    }
+                }
+            }
+            self.outputs = outputs;
+        }
+
+        for &subsystem in changed {
+            match subsystem {
+                Database => self.events.push_back(Event::DatabaseUpdated),
+                Player | Mixer | Options | Queue | Output => (),
+                other => self.events.push_back(Event::Other(other)),
+            }
+        }
+        Ok(())
+    }
+
+    fn diff_status(&mut self, status: Status) {
+        let old = &self.status;
+        if status.state != old.state {
+            self.events.push_back(Event::StateChanged(status.state));
+        }
+        if status.volume != old.volume {
+            self.events.push_back(Event::VolumeChanged(status.volume));
+        }
+        let options = |s: &Status| (s.repeat, s.random, s.single, s.consume, s.crossfade, s.replaygain);
+        if options(&status) != options(old) {
+            self.events.push_back(Event::OptionsChanged {
+                repeat: status.repeat,
+                random: status.random,
+                single: status.single,
+                consume: status.consume,
+                crossfade: status.crossfade,
+                replaygain: status.replaygain,
+            });
+        }
+        if status.queue_version != old.queue_version {
+            self.events.push_back(Event::QueueChanged {
+                version: status.queue_version,
+            });
+        }
+        self.status = status;
+    }
+}
+
+impl<S: Read + Write> Iterator for Watcher<S> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        if self.done {
+            return None;
+        }
+        let event = self.next_event();
+        if let Err(ref e) = event {
+            self.done = e.is_broken();
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Watcher};
+    use crate::client::Client;
+    use crate::connect::ConnectOptions;
+    use crate::error::Error;
+    use crate::idle::Subsystem;
+    use crate::status::{ReplayGain, State};
+    use crate::test_util::Mock;
+    use std::time::Duration;
+
+    #[test]
+    fn events() {
+        let responses = [
+            // status, currentsong and outputs on start
+            "OK MPD 0.23.0\n",
+            "volume: 50\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 2\nplaylistlength: 1\nstate: stop\nreplay_gain_mode: off\nOK\n",
+            "OK\n",
+            "outputid: 0\noutputname: alsa\noutputenabled: 1\noutputid: 1\noutputname: httpd\noutputenabled: 0\nOK\n",
+            // playback started
+            "changed: player\nchanged: mixer\nOK\n",
+            "volume: 60\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 2\nplaylistlength: 1\nstate: play\nsong: 0\nsongid: 1\nreplay_gain_mode: off\nOK\n",
+            "file: a.flac\nPos: 0\nId: 1\nOK\n",
+            // options and outputs changed, a song was added
+            "changed: options\nchanged: output\nchanged: playlist\nchanged: database\nchanged: sticker\nOK\n",
+            "volume: 60\nrepeat: 1\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 3\nplaylistlength: 2\nstate: play\nsong: 0\nsongid: 1\nreplay_gain_mode: album\nOK\n",
+            "file: a.flac\nPos: 0\nId: 1\nOK\n",
+            "outputid: 0\noutputname: alsa\noutputenabled: 1\noutputid: 1\noutputname: httpd\noutputenabled: 1\nOK\n",
+            // a subsystem unknown to the library doesn't hide other changes
+            "changed: partition\nchanged: player\nOK\n",
+            "volume: 60\nrepeat: 1\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 3\nplaylistlength: 2\nstate: pause\nsong: 0\nsongid: 1\nreplay_gain_mode: album\nOK\n",
+            "file: a.flac\nPos: 0\nId: 1\nOK\n",
+        ];
+        let mock = Mock::new(responses.concat().as_bytes());
+        let mut watcher = Watcher::new(Client::new(mock).unwrap()).unwrap();
+        assert_eq!(watcher.outputs().len(), 2);
+
+        match watcher.next_event().unwrap() {
+            Event::SongChanged {
+                from: None,
+                to: Some(song),
+            } => assert_eq!(song.file, "a.flac"),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(watcher.next_event().unwrap(), Event::StateChanged(State::Play));
+        assert_eq!(watcher.next_event().unwrap(), Event::VolumeChanged(60));
+
+        assert!(matches!(
+            watcher.next_event().unwrap(),
+            Event::OptionsChanged {
+                repeat: true,
+                random: false,
+                replaygain: Some(ReplayGain::Album),
+                ..
+            }
+        ));
+        assert_eq!(watcher.next_event().unwrap(), Event::QueueChanged { version: 3 });
+        assert_eq!(watcher.next_event().unwrap(), Event::OutputToggled(1, true));
+        assert_eq!(watcher.next_event().unwrap(), Event::DatabaseUpdated);
+        assert_eq!(watcher.next_event().unwrap(), Event::Other(Subsystem::Sticker));
+        assert_eq!(watcher.status().queue_len, 2);
+        assert_eq!(watcher.next().unwrap().unwrap(), Event::StateChanged(State::Pause));
+
+        // The iterator ends once the connection is closed
+        assert!(matches!(watcher.next(), Some(Err(Error::Io(_)))));
+        assert!(watcher.next().is_none());
+
+        let mpd = watcher.into_inner();
+        assert_eq!(
+            mpd.socket().written(),
+            format!(
+                "{status}currentsong\noutputs\nidle\n{status}currentsong\nidle\n{status}currentsong\noutputs\n\
+                 idle\n{status}currentsong\nidle\n",
+                status = "command_list_begin\nstatus\nreplay_gain_status\ncommand_list_end\n"
+            )
+        );
+    }
+    #[test]
+    fn timeout() {
+        let responses = [
+            "OK MPD 0.23.0\n",
+            "volume: 50\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 2\nplaylistlength: 1\nstate: stop\nreplay_gain_mode: off\nOK\n",
+            "OK\n",
+            "OK\n",
+        ];
+        let mock = Mock::new(responses.concat().as_bytes()).time_out();
+        let options = ConnectOptions::new().read_timeout(Duration::from_secs(1)).clone();
+        let mut watcher = Watcher::new(Client::with_options(mock, &options).unwrap()).unwrap();
+
+        // The server may still be idling, so nothing is sent after a timeout
+        assert!(matches!(watcher.next(), Some(Err(Error::Timeout))));
+        assert!(watcher.next().is_none());
+        assert!(watcher.into_inner().socket().written().ends_with("outputs\nidle\n"));
+    }
+}
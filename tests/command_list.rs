@@ -0,0 +1,54 @@
+extern crate mpd;
+
+mod helpers;
+use helpers::connect;
+
+#[test]
+fn command_list() {
+    let mut mpd = connect();
+    let mut list = mpd.command_list();
+    let add = list.add("empty.flac");
+    let id = list.push("empty.flac");
+    let status = list.status();
+    let mut replies = list.run().unwrap();
+
+    assert!(replies.error().is_none());
+    replies.take(add).unwrap();
+    assert!(replies.take(id).is_ok());
+    assert_eq!(replies.take(status).unwrap().queue_len, 2);
+}
+
+#[test]
+fn command_list_error() {
+    let mut mpd = connect();
+    let mut list = mpd.command_list();
+    let ok = list.status();
+    let failed = list.add("does-not-exist.flac");
+    let skipped = list.currentsong();
+    let mut replies = list.run().unwrap();
+
+    assert_eq!(replies.error().map(|e| e.pos), Some(1));
+    assert!(replies.take(ok).is_ok());
+    assert!(matches!(replies.take(failed), Err(mpd::error::Error::Server(_))));
+    assert!(matches!(replies.take(skipped), Err(mpd::error::Error::Proto(mpd::error::ProtoError::Skipped))));
+
+    mpd.ping().unwrap();
+}
+
+#[test]
+fn pipeline() {
+    let mut mpd = connect();
+    let mut pipeline = mpd.pipeline();
+    let failed = pipeline.add("does-not-exist.flac");
+    let id = pipeline.push("empty.flac");
+    let queue = pipeline.queue();
+    let _status = pipeline.status();
+    let mut pending = pipeline.send().unwrap();
+
+    assert_eq!(pending.take(queue).unwrap().len(), 1);
+    assert!(matches!(pending.take(failed), Err(mpd::error::Error::Server(_))));
+    assert!(pending.take(id).is_ok());
+    drop(pending);
+
+    mpd.ping().unwrap();
+}
@@ -135,8 +135,7 @@ impl Daemon {
     }
 
     fn maybe_connect(&self) -> Result<mpd::Client<UnixStream>, mpd::error::Error> {
-        let stream = UnixStream::connect(&self.config.sock_path)?;
-        mpd::Client::new(stream)
+        mpd::Client::connect_unix(&self.config.sock_path)
     }
 
     pub fn connect(&self) -> mpd::Client<UnixStream> {
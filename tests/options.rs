@@ -62,3 +62,13 @@ fn crossfade() {
         }
     );
 }
+
+#[test]
+fn binarylimit() {
+    let mut mpd = connect();
+    assert_eq!(mpd.binary_limit(), 8192);
+    if mpd.version >= mpd::Version(0, 22, 4) {
+        mpd.binarylimit(65536).unwrap();
+        assert_eq!(mpd.binary_limit(), 65536);
+    }
+}
@@ -26,3 +26,13 @@ fn tagtypes() {
     let mut mpd = connect();
     println!("{:?}", mpd.tagtypes().unwrap());
 }
+
+#[test]
+fn raw_command() {
+    let mut mpd = connect();
+    let status = mpd.raw_command("status", &[]).unwrap();
+    assert!(status.get("state").is_some());
+
+    assert!(mpd.raw_command("no_such_command", &["arg"]).is_err());
+    mpd.ping().unwrap();
+}
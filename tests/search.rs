@@ -9,7 +9,46 @@ fn search() {
     let mut mpd = connect();
     let mut query = FilterQuery::new();
     query.and(mpd::Term::Any, "Soul");
-    let songs = mpd.find(&Query::Filters(query), None);
+    let songs = mpd.find(&Query::Filters(query), None, None);
     println!("{:?}", songs);
     assert!(songs.is_ok());
 }
+
+#[test]
+fn count() {
+    let mut mpd = connect();
+    let mut query = FilterQuery::new();
+    query.and(mpd::Term::File, "empty.flac");
+    let counts = mpd.count(&Query::Filters(query), None).unwrap();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].group, None);
+    assert_eq!(counts[0].songs, 1);
+}
+
+#[test]
+fn list_grouped() {
+    let mut mpd = connect();
+    let groups = mpd
+        .list_grouped(&mpd::Term::File, &Query::Filters(FilterQuery::new()), &[mpd::Term::Tag("albumartist")], None)
+        .unwrap();
+    // The only song has no tags, so it's listed under an empty album artist
+    assert_eq!(groups.len(), 1);
+    assert!(groups[0].tag.eq_ignore_ascii_case("albumartist"));
+    assert_eq!(groups[0].value, "");
+    assert!(groups[0].groups.is_empty());
+    assert_eq!(groups[0].values, vec!["empty.flac"]);
+}
+
+#[test]
+fn searchadd() {
+    let mut mpd = connect();
+    let mut query = FilterQuery::new();
+    query.and(mpd::Term::File, "empty.flac");
+    let query = Query::Filters(query);
+    mpd.searchadd(&query, None, None, None).unwrap();
+    mpd.findadd(&query, None, None, Some(mpd::Position::Absolute(0))).unwrap();
+    assert_eq!(mpd.queue().unwrap().len(), 2);
+
+    mpd.searchaddpl("searchadd", &query, None, None, None).unwrap();
+    assert_eq!(mpd.playlist("searchadd").unwrap().len(), 1);
+}
@@ -26,3 +26,32 @@ fn rescan_update() {
     println!("update: {:?}", mpd.update());
     println!("rescan: {:?}", mpd.rescan());
 }
+
+#[test]
+fn listallinfo() {
+    let mut mpd = connect();
+    let songs = mpd.listallinfo("").unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(songs.iter().any(|s| match s {
+        mpd::lsinfo::LsInfoResponse::Song(song) => song.file == "empty.flac",
+        _ => false,
+    }));
+}
+
+#[test]
+fn listall_drop_early() {
+    let mut mpd = connect();
+    assert!(mpd.listall("").unwrap().next().is_some());
+    mpd.ping().unwrap();
+}
+
+#[test]
+fn cover_art_missing() {
+    let mut mpd = connect();
+    assert_eq!(mpd.readpicture("empty.flac").unwrap(), None);
+    assert_eq!(mpd.albumart("empty.flac").unwrap(), None);
+    assert_eq!(mpd.cover_art("empty.flac").unwrap(), None);
+
+    let mut out = Vec::new();
+    assert_eq!(mpd.readpicture_to("empty.flac", 0, &mut out, |_, _| ()).unwrap(), None);
+    assert!(out.is_empty());
+}